@@ -1,25 +1,79 @@
-use bevy::{
-    input::{mouse::MouseButtonInput, ElementState},
-    math::Vec3Swizzles,
-    prelude::*,
-};
-use bevy_rapier2d::{physics::{ColliderBundle, ColliderPositionSync, RigidBodyBundle, RigidBodyPositionSync}, prelude::{
-        ColliderShape, ColliderType, RigidBodyActivation, RigidBodyPosition, RigidBodyType,
+use bevy::{math::Vec3Swizzles, prelude::*};
+use bevy_rapier2d::{
+    physics::{ColliderBundle, IntoEntity, RigidBodyBundle, RigidBodyPositionSync},
+    prelude::{
+        ActiveEvents, ColliderFlags, ColliderMaterial, ColliderShape, ColliderType, ContactEvent,
+        InteractionGroups, RigidBodyActivation, RigidBodyPosition, RigidBodyType,
         RigidBodyVelocity,
-    }, render::ColliderDebugRender};
+    },
+    render::ColliderDebugRender,
+};
+
+use crate::{
+    assets::GameAssets,
+    combat::Damage,
+    coords::{pixels_to_physics, world_to_physics},
+    input::{Action, ActionState},
+    map::Editor,
+    physics::CollisionLayer,
+    player::{Player, PlayerMouse},
+    weapons::{Weapon, WeaponDefinition},
+};
 
-use crate::{assets::GameAssets, physics::PHYSICS_SCALE, player::{Player, PlayerMouse}};
+#[derive(Debug, Eq, PartialEq, PartialOrd, Clone, Copy, Hash, SystemLabel)]
+struct BulletSpawn;
 
 pub struct BulletsPlugin;
 
 impl Plugin for BulletsPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_system(spawn_bullet.system());
+        app.init_resource::<BulletPool>();
+        app.add_system(spawn_bullet.system().label(BulletSpawn));
+        // Bullets retired this frame only count as pooled once the commands have been applied
+        app.add_system(expire_bullets.system().after(BulletSpawn));
+    }
+}
+
+pub struct Bullet {
+    lifetime: Timer,
+    bounces_left: u32,
+    /// Set once it hit something without any bounces left
+    spent: bool,
+}
+
+impl Bullet {
+    fn new(definition: &WeaponDefinition) -> Self {
+        Bullet {
+            lifetime: Timer::from_seconds(definition.projectile_lifetime, false),
+            bounces_left: definition.projectile_bounces,
+            spent: false,
+        }
+    }
+}
+
+/// A bullet that is done flying and waits in the `BulletPool` to be fired again
+pub struct Pooled;
+
+/// Bullets that are done flying.
+///
+/// They are kept around hidden, static, asleep and without collisions instead of being despawned,
+/// so sustained fire does not keep creating and removing entities and their rapier bodies.
+#[derive(Default)]
+pub struct BulletPool {
+    bullets: Vec<Entity>,
+}
+
+fn bullet_collider_flags() -> ColliderFlags {
+    ColliderFlags {
+        active_events: ActiveEvents::CONTACT_EVENTS,
+        ..CollisionLayer::PlayerProjectile.flags()
     }
 }
 
 #[derive(Bundle)]
 struct BulletBundle {
+    bullet: Bullet,
+    damage: Damage,
     #[bundle]
     sprite_bundle: SpriteSheetBundle,
     #[bundle]
@@ -30,25 +84,42 @@ struct BulletBundle {
 }
 
 impl BulletBundle {
-    fn new(texture_atlas_handle: Handle<TextureAtlas>, position: Vec3) -> BulletBundle {
+    fn new(
+        texture_atlas_handle: Handle<TextureAtlas>,
+        position: Vec3,
+        definition: &WeaponDefinition,
+        shooter: Entity,
+    ) -> BulletBundle {
         BulletBundle {
+            bullet: Bullet::new(definition),
+            damage: Damage {
+                amount: definition.damage,
+                attacker: shooter,
+            },
             sprite_bundle: SpriteSheetBundle {
                 texture_atlas: texture_atlas_handle,
-                sprite: TextureAtlasSprite::new(64),
+                sprite: TextureAtlasSprite::new(definition.sprite_index),
                 transform: Transform::from_translation(position),
                 ..Default::default()
             },
             rigid_body_bundle: RigidBodyBundle {
                 body_type: RigidBodyType::Dynamic,
                 position: RigidBodyPosition {
-                    position: (position / PHYSICS_SCALE) .into(),
+                    position: world_to_physics(position.xy()).extend(0.0).into(),
                     ..Default::default()
                 },
                 ..Default::default()
             },
             collider_bundle: ColliderBundle {
-                shape: ColliderShape::ball(0.1),
+                shape: ColliderShape::ball(pixels_to_physics(definition.projectile_radius)),
                 collider_type: ColliderType::Solid,
+                flags: bullet_collider_flags(),
+                // Bounces keep the bullet's speed
+                material: ColliderMaterial {
+                    friction: 0.,
+                    restitution: 1.,
+                    ..Default::default()
+                },
                 ..Default::default()
             },
             update_from_rigid: RigidBodyPositionSync::Interpolated { prev_pos: None },
@@ -57,7 +128,7 @@ impl BulletBundle {
 
     fn with_bullet_impulse(mut self, impulse: Vec2) -> BulletBundle {
         self.rigid_body_bundle.velocity = RigidBodyVelocity {
-            linvel: (impulse / PHYSICS_SCALE).into(),
+            linvel: world_to_physics(impulse).into(),
             ..Default::default()
         };
 
@@ -67,20 +138,196 @@ impl BulletBundle {
 
 fn spawn_bullet(
     mut commands: Commands,
-    mut mouse_clicks: EventReader<MouseButtonInput>,
+    action_state: Res<ActionState>,
     game_assets: Res<GameAssets>,
-    player_query: Query<&Transform, With<Player>>,
-    mouse_query: Query<&Transform, With<PlayerMouse>>,
+    editor: Res<Editor>,
+    definitions: Res<Assets<WeaponDefinition>>,
+    mut pool: ResMut<BulletPool>,
+    mut queries: QuerySet<(
+        Query<(Entity, &Transform, &PlayerMouse, &mut Weapon), With<Player>>,
+        Query<
+            (
+                &mut Bullet,
+                &mut Damage,
+                &mut Transform,
+                &mut TextureAtlasSprite,
+                &mut Visible,
+                &mut RigidBodyPosition,
+                &mut RigidBodyVelocity,
+                &mut RigidBodyType,
+                &mut RigidBodyActivation,
+                &mut RigidBodyPositionSync,
+                &mut ColliderShape,
+                &mut ColliderFlags,
+            ),
+            With<Pooled>,
+        >,
+    )>,
+) {
+    // Clicks paint tiles while editing the room
+    if editor.enabled {
+        return;
+    }
+
+    let (shooter, translation, mouse_position, definition, angles) =
+        if let Ok((shooter, player, mouse, mut weapon)) = queries.q0_mut().single_mut() {
+            let definition = if let Some(definition) = definitions.get(&weapon.definition) {
+                definition
+            } else {
+                return;
+            };
+
+            if !weapon.pull_trigger(
+                definition,
+                action_state.pressed(Action::Fire),
+                action_state.just_pressed(Action::Fire),
+            ) {
+                return;
+            }
+
+            (
+                shooter,
+                player.translation,
+                mouse.position(),
+                definition,
+                weapon.fire(definition),
+            )
+        } else {
+            return;
+        };
+
+    let direction = mouse_position - translation.xy();
+    if direction == Vec2::ZERO {
+        return;
+    }
+    let direction = direction.normalize().extend(0.);
+
+    for angle in angles {
+        let velocity = Quat::from_rotation_z(angle.to_radians()) * direction;
+        let impulse = velocity.xy() * definition.projectile_speed;
+
+        // Skip over pooled bullets that have been despawned in the meantime
+        let pooled = std::iter::from_fn(|| pool.bullets.pop())
+            .find(|entity| queries.q1_mut().get_mut(*entity).is_ok());
+
+        if let Some(entity) = pooled {
+            let (
+                mut bullet,
+                mut damage,
+                mut transform,
+                mut sprite,
+                mut visible,
+                mut rigid_body_position,
+                mut rigid_body_velocity,
+                mut body_type,
+                mut activation,
+                mut position_sync,
+                mut shape,
+                mut flags,
+            ) = queries.q1_mut().get_mut(entity).unwrap();
+
+            // bevy_rapier hands every body and collider component changed since its last step over
+            // to the physics pipeline, so writing them here moves and reshapes the rapier body
+            *bullet = Bullet::new(definition);
+            *damage = Damage {
+                amount: definition.damage,
+                attacker: shooter,
+            };
+            transform.translation = translation;
+            sprite.index = definition.sprite_index;
+            visible.is_visible = true;
+
+            rigid_body_position.position = world_to_physics(translation.xy()).extend(0.0).into();
+            rigid_body_position.next_position = rigid_body_position.position;
+            *rigid_body_velocity = RigidBodyVelocity {
+                linvel: world_to_physics(impulse).into(),
+                ..Default::default()
+            };
+            *body_type = RigidBodyType::Dynamic;
+            activation.wake_up(true);
+            // Otherwise the sprite gets interpolated from where the bullet was retired
+            *position_sync = RigidBodyPositionSync::Interpolated { prev_pos: None };
+
+            // A new shape has to be put into the broad phase again, so it is only replaced for weapons
+            // with differently sized projectiles
+            let radius = pixels_to_physics(definition.projectile_radius);
+            if shape.as_ball().map(|ball| ball.radius) != Some(radius) {
+                *shape = ColliderShape::ball(radius);
+            }
+            *flags = bullet_collider_flags();
+
+            commands.entity(entity).remove::<Pooled>();
+            continue;
+        }
+
+        commands
+            .spawn()
+            .insert_bundle(
+                BulletBundle::new(
+                    game_assets.texture_atlas_handle.clone(),
+                    translation,
+                    definition,
+                    shooter,
+                )
+                .with_bullet_impulse(impulse),
+            )
+            .insert(ColliderDebugRender::default());
+    }
+}
+
+/// Retires bullets into the pool once their lifetime ran out or they hit something they cannot
+/// bounce off anymore
+fn expire_bullets(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut pool: ResMut<BulletPool>,
+    mut contact_events: EventReader<ContactEvent>,
+    mut bullet_query: Query<
+        (
+            Entity,
+            &mut Bullet,
+            &mut Visible,
+            &mut ColliderFlags,
+            &mut RigidBodyVelocity,
+            &mut RigidBodyType,
+            &mut RigidBodyActivation,
+        ),
+        Without<Pooled>,
+    >,
 ) {
-    let player = player_query.single().unwrap();
-    let mouse_position = mouse_query.single().unwrap();
-    for ev in mouse_clicks.iter() {
-        if ev.button == MouseButton::Left && ev.state == ElementState::Pressed {
-            let direction = mouse_position.translation - player.translation;
-            commands.spawn().insert_bundle(
-                BulletBundle::new(game_assets.texture_atlas_handle.clone(), player.translation)
-                    .with_bullet_impulse(direction.xy().normalize() * 1000.),
-            ).insert(ColliderDebugRender::default());
+    for event in contact_events.iter() {
+        let (first, second) = match event {
+            ContactEvent::Started(first, second) => (first.entity(), second.entity()),
+            ContactEvent::Stopped(..) => continue,
+        };
+
+        for entity in [first, second].iter() {
+            if let Ok((_, mut bullet, ..)) = bullet_query.get_mut(*entity) {
+                if bullet.bounces_left == 0 {
+                    bullet.spent = true;
+                } else {
+                    bullet.bounces_left -= 1;
+                }
+            }
+        }
+    }
+
+    for (entity, mut bullet, mut visible, mut flags, mut velocity, mut body_type, mut activation) in
+        bullet_query.iter_mut()
+    {
+        if !bullet.lifetime.tick(time.delta()).finished() && !bullet.spent {
+            continue;
         }
+
+        visible.is_visible = false;
+        flags.collision_groups = InteractionGroups::none();
+        flags.solver_groups = InteractionGroups::none();
+        *velocity = RigidBodyVelocity::default();
+        // Static and asleep, rapier stops integrating the body until it gets fired again
+        *body_type = RigidBodyType::Static;
+        activation.sleep();
+
+        commands.entity(entity).insert(Pooled);
+        pool.bullets.push(entity);
     }
 }
@@ -0,0 +1,161 @@
+use bevy::{math::Vec3Swizzles, prelude::*};
+use bevy_rapier2d::{
+    physics::IntoEntity,
+    prelude::{ColliderHandle, ContactEvent, IntersectionEvent, NarrowPhase},
+};
+
+use crate::{coords::physics_to_world, player::Invulnerable};
+
+/// Systems reading `HitEvent`s should run after this to see them in the same frame
+#[derive(Debug, Eq, PartialEq, PartialOrd, Clone, Copy, Hash, SystemLabel)]
+pub struct HitDetection;
+
+pub struct CombatPlugin;
+
+impl Plugin for CombatPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<HitEvent>();
+        app.add_system(detect_hits.system().label(HitDetection));
+        app.add_system(apply_damage.system().after(HitDetection));
+    }
+}
+
+/// Hurts whatever it runs into, e.g. on a bullet
+#[derive(Debug, Clone, Copy)]
+pub struct Damage {
+    pub amount: u32,
+    /// Who gets credited for the hits, it cannot hurt itself
+    pub attacker: Entity,
+}
+
+/// How much more damage an entity can take
+#[derive(Debug, Clone, Copy)]
+pub struct Health {
+    pub current: u32,
+    pub max: u32,
+}
+
+impl Health {
+    pub fn new(max: u32) -> Self {
+        Health { current: max, max }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current == 0
+    }
+}
+
+/// Something with `Damage` ran into `target`, it is sent for walls as well
+#[derive(Debug, Clone, Copy)]
+pub struct HitEvent {
+    pub attacker: Entity,
+    pub target: Entity,
+    pub damage: u32,
+    /// Where the hit landed in world pixels
+    pub point: Vec2,
+    /// Points away from the target's surface at `point`, zero if it is unknown
+    pub normal: Vec2,
+}
+
+/// Where two colliders touch in world pixels, and the normal of the second one's surface there
+fn contact_point(
+    narrow_phase: &NarrowPhase,
+    first: ColliderHandle,
+    second: ColliderHandle,
+) -> Option<(Vec2, Vec2)> {
+    let pair = narrow_phase.contact_pair(first, second)?;
+    let manifold = pair
+        .manifolds
+        .iter()
+        .find(|manifold| !manifold.data.solver_contacts.is_empty())?;
+
+    let point = manifold.data.solver_contacts[0].point;
+    // The normal points away from the pair's first collider
+    let normal = if pair.collider1 == first {
+        -manifold.data.normal
+    } else {
+        manifold.data.normal
+    };
+
+    Some((
+        physics_to_world(Vec2::new(point.x, point.y)),
+        Vec2::new(normal.x, normal.y),
+    ))
+}
+
+fn detect_hits(
+    narrow_phase: Res<NarrowPhase>,
+    mut contact_events: EventReader<ContactEvent>,
+    mut intersection_events: EventReader<IntersectionEvent>,
+    mut hit_events: EventWriter<HitEvent>,
+    damage_query: Query<(&Damage, &Transform)>,
+    target_query: Query<(Option<&Transform>, Option<&Invulnerable>)>,
+) {
+    // Both ways around, as either of the colliders could be dealing damage
+    let mut touches = vec![];
+
+    for event in contact_events.iter() {
+        let (first, second) = match event {
+            ContactEvent::Started(first, second) => (*first, *second),
+            ContactEvent::Stopped(..) => continue,
+        };
+
+        touches.push((first, second, contact_point(&narrow_phase, first, second)));
+        touches.push((second, first, contact_point(&narrow_phase, second, first)));
+    }
+
+    for event in intersection_events.iter() {
+        if event.intersecting {
+            touches.push((event.collider1, event.collider2, None));
+            touches.push((event.collider2, event.collider1, None));
+        }
+    }
+
+    for (source, target, contact) in touches {
+        let (damage, transform) = if let Ok(source) = damage_query.get(source.entity()) {
+            source
+        } else {
+            continue;
+        };
+
+        let target = target.entity();
+        if target == damage.attacker {
+            continue;
+        }
+
+        let target_transform = match target_query.get(target) {
+            Ok((target_transform, None)) => target_transform,
+            _ => continue,
+        };
+
+        // Sensors do not report where they got touched, so it is guessed from the centers
+        let (point, normal) = contact.unwrap_or_else(|| {
+            let point = transform.translation.xy();
+            let offset = target_transform.map_or(Vec2::ZERO, |target_transform| {
+                point - target_transform.translation.xy()
+            });
+
+            if offset == Vec2::ZERO {
+                (point, Vec2::ZERO)
+            } else {
+                (point, offset.normalize())
+            }
+        });
+
+        hit_events.send(HitEvent {
+            attacker: damage.attacker,
+            target,
+            damage: damage.amount,
+            point,
+            normal,
+        });
+    }
+}
+
+fn apply_damage(mut hit_events: EventReader<HitEvent>, mut health_query: Query<&mut Health>) {
+    for hit in hit_events.iter() {
+        if let Ok(mut health) = health_query.get_mut(hit.target) {
+            health.current = health.current.saturating_sub(hit.damage);
+        }
+    }
+}
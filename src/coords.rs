@@ -0,0 +1,157 @@
+use bevy::prelude::*;
+
+use crate::physics::PHYSICS_SCALE;
+
+/// Where the tilemap of the current room sits in the world.
+///
+/// Tile `(0, 0)` is the bottom left tile, its lower left corner sits at `origin`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MapGeometry {
+    pub origin: Vec2,
+    pub tile_size: Vec2,
+    pub size: UVec2,
+    /// The depth between the decoration and the overhead layers, where players and bullets live
+    pub actor_depth: f32,
+}
+
+impl MapGeometry {
+    /// The center of the tile in world pixels
+    pub fn tile_to_world(&self, tile: UVec2) -> Vec2 {
+        self.origin + (tile.as_f32() + Vec2::splat(0.5)) * self.tile_size
+    }
+
+    /// The tile containing the given point, `None` if it lies outside of the map
+    pub fn world_to_tile(&self, world: Vec2) -> Option<UVec2> {
+        let tile = ((world - self.origin) / self.tile_size).floor();
+
+        if tile.x < 0. || tile.y < 0. || tile.x >= self.size.x as f32 || tile.y >= self.size.y as f32
+        {
+            return None;
+        }
+
+        Some(UVec2::new(tile.x as u32, tile.y as u32))
+    }
+
+    /// The center and half extents in world pixels of a rectangle of tiles starting at `start`
+    pub fn tile_rect_to_world(&self, start: UVec2, size: UVec2) -> (Vec2, Vec2) {
+        let half_extents = size.as_f32() * self.tile_size / 2.;
+        let center = self.origin + start.as_f32() * self.tile_size + half_extents;
+
+        (center, half_extents)
+    }
+}
+
+/// Converts a position or length in world pixels into rapier units
+pub fn world_to_physics(world: Vec2) -> Vec2 {
+    world / PHYSICS_SCALE
+}
+
+/// Converts a position or length in rapier units back into world pixels
+pub fn physics_to_world(physics: Vec2) -> Vec2 {
+    physics * PHYSICS_SCALE
+}
+
+/// Converts a single length in world pixels, e.g. a radius, into rapier units
+pub fn pixels_to_physics(pixels: f32) -> f32 {
+    pixels / PHYSICS_SCALE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn geometry() -> MapGeometry {
+        MapGeometry {
+            origin: Vec2::new(-128., -96.),
+            tile_size: Vec2::new(16., 16.),
+            size: UVec2::new(16, 12),
+            actor_depth: 1.5,
+        }
+    }
+
+    #[test]
+    fn tiles_round_trip_through_world() {
+        let geometry = geometry();
+
+        for y in 0..geometry.size.y {
+            for x in 0..geometry.size.x {
+                let tile = UVec2::new(x, y);
+                assert_eq!(
+                    geometry.world_to_tile(geometry.tile_to_world(tile)),
+                    Some(tile)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn tile_centers_are_offset_by_half_a_tile() {
+        let geometry = geometry();
+
+        assert_eq!(
+            geometry.tile_to_world(UVec2::new(0, 0)),
+            Vec2::new(-120., -88.)
+        );
+        assert_eq!(geometry.tile_to_world(UVec2::new(8, 6)), Vec2::new(8., 8.));
+    }
+
+    #[test]
+    fn tile_edges_belong_to_the_upper_tile() {
+        let geometry = geometry();
+
+        assert_eq!(
+            geometry.world_to_tile(Vec2::new(-128., -96.)),
+            Some(UVec2::new(0, 0))
+        );
+        assert_eq!(
+            geometry.world_to_tile(Vec2::new(-112., -80.)),
+            Some(UVec2::new(1, 1))
+        );
+    }
+
+    #[test]
+    fn positions_outside_of_the_map_have_no_tile() {
+        let geometry = geometry();
+
+        // Left of and below the origin, where the tile coordinates would be negative
+        assert_eq!(geometry.world_to_tile(Vec2::new(-128.5, 0.)), None);
+        assert_eq!(geometry.world_to_tile(Vec2::new(0., -96.5)), None);
+        assert_eq!(geometry.world_to_tile(Vec2::new(-1000., -1000.)), None);
+
+        // The upper and right edges of the map already lie outside of it
+        assert_eq!(geometry.world_to_tile(Vec2::new(128., 0.)), None);
+        assert_eq!(geometry.world_to_tile(Vec2::new(0., 96.)), None);
+    }
+
+    #[test]
+    fn tile_rects_are_centered_on_their_tiles() {
+        let geometry = geometry();
+
+        let (center, half_extents) =
+            geometry.tile_rect_to_world(UVec2::new(0, 0), UVec2::new(1, 1));
+        assert_eq!(center, geometry.tile_to_world(UVec2::new(0, 0)));
+        assert_eq!(half_extents, Vec2::new(8., 8.));
+
+        let (center, half_extents) =
+            geometry.tile_rect_to_world(UVec2::new(2, 3), UVec2::new(4, 2));
+        assert_eq!(center, Vec2::new(-128. + 32. + 32., -96. + 48. + 16.));
+        assert_eq!(half_extents, Vec2::new(32., 16.));
+    }
+
+    #[test]
+    fn physics_conversions_are_inverses() {
+        for world in [
+            Vec2::ZERO,
+            Vec2::new(16., -16.),
+            Vec2::new(-120., 88.),
+            Vec2::new(0.5, 1234.25),
+        ]
+        .iter()
+        {
+            assert_eq!(physics_to_world(world_to_physics(*world)), *world);
+        }
+
+        assert_eq!(world_to_physics(Vec2::splat(PHYSICS_SCALE)), Vec2::ONE);
+        assert_eq!(pixels_to_physics(PHYSICS_SCALE * 2.), 2.);
+    }
+}
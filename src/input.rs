@@ -0,0 +1,299 @@
+use std::collections::BTreeMap;
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    input::InputSystem,
+    prelude::*,
+    reflect::TypeUuid,
+    utils::{BoxedFuture, HashMap, HashSet},
+};
+use serde::{Deserialize, Serialize};
+
+/// Where the bindings get loaded from, the built in ones are used until it has been loaded
+pub const BINDINGS_PATH: &str = "input.bindings";
+/// The bindings the game ships with, so the built in ones cannot drift apart from the file
+const DEFAULT_BINDINGS: &str = include_str!("../assets/input.bindings");
+
+pub struct ActionsPlugin;
+
+impl Plugin for ActionsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_asset::<InputBindings>();
+        app.init_asset_loader::<InputBindingsLoader>();
+        app.init_resource::<InputBindings>();
+        app.init_resource::<ActionState>();
+        app.add_startup_system(load_bindings.system());
+        app.add_system(apply_loaded_bindings.system());
+        app.add_system_to_stage(
+            CoreStage::PreUpdate,
+            update_action_state.system().after(InputSystem),
+        );
+    }
+}
+
+/// Everything the player can do, the game only ever asks for these instead of keys or buttons
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Fire,
+    Dash,
+    NextWeapon,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AxisDirection {
+    Positive,
+    Negative,
+}
+
+/// Something on a keyboard, mouse or gamepad that triggers an action
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    GamepadButton(GamepadButtonType),
+    /// A stick or trigger pushed past the deadzone in the given direction
+    GamepadAxis(GamepadAxisType, AxisDirection),
+}
+
+/// Which bindings trigger which action, loaded from `input.bindings`.
+///
+/// While it is a resource it holds the bindings in use, changing it rebinds the actions right
+/// away. On native builds the file is watched, so editing it rebinds them while playing.
+#[derive(Debug, Clone, TypeUuid, Serialize, Deserialize)]
+#[uuid = "c4e1a9b2-3d7f-4a60-8e25-f19b0d6c7a38"]
+pub struct InputBindings {
+    /// Sticks and triggers closer to their center than this are not pushed
+    #[serde(default = "InputBindings::default_deadzone")]
+    pub deadzone: f32,
+    bindings: BTreeMap<Action, Vec<Binding>>,
+}
+
+impl InputBindings {
+    fn default_deadzone() -> f32 {
+        0.2
+    }
+
+    pub fn bindings(&self, action: Action) -> &[Binding] {
+        self.bindings
+            .get(&action)
+            .map_or(&[][..], |bindings| bindings.as_slice())
+    }
+}
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        ron::de::from_str(DEFAULT_BINDINGS).expect("The shipped input bindings are invalid")
+    }
+}
+
+#[derive(Default)]
+pub struct InputBindingsLoader;
+
+impl AssetLoader for InputBindingsLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let bindings: InputBindings = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(bindings));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["bindings"]
+    }
+}
+
+/// Keeps the bindings file loaded
+struct BindingsFile(Handle<InputBindings>);
+
+fn load_bindings(mut commands: Commands, asset_server: Res<AssetServer>) {
+    #[cfg(feature = "native")]
+    if let Err(error) = asset_server.watch_for_changes() {
+        warn!("Changes to {} need a restart: {:?}", BINDINGS_PATH, error);
+    }
+
+    commands.insert_resource(BindingsFile(asset_server.load(BINDINGS_PATH)));
+}
+
+fn apply_loaded_bindings(
+    mut binding_events: EventReader<AssetEvent<InputBindings>>,
+    loaded_bindings: Res<Assets<InputBindings>>,
+    bindings_file: Res<BindingsFile>,
+    mut bindings: ResMut<InputBindings>,
+) {
+    for event in binding_events.iter() {
+        match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle }
+                if *handle == bindings_file.0 =>
+            {
+                if let Some(loaded) = loaded_bindings.get(handle) {
+                    info!("Loaded input bindings from {}", BINDINGS_PATH);
+                    *bindings = loaded.clone();
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// What the player is doing this frame, read this instead of the keyboard, mouse or gamepad
+#[derive(Default, Debug)]
+pub struct ActionState {
+    pressed: HashSet<Action>,
+    previously_pressed: HashSet<Action>,
+    /// How far each action is pushed, from 0 to 1, buttons are always pushed all the way
+    values: HashMap<Action, f32>,
+    deadzone: f32,
+    gamepad: Option<Gamepad>,
+    using_gamepad: bool,
+    aim: Option<Vec2>,
+}
+
+impl ActionState {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    /// How far the action is pushed from 0 to 1, e.g. how far a stick is tilted
+    pub fn value(&self, action: Action) -> f32 {
+        self.values.get(&action).copied().unwrap_or(0.)
+    }
+
+    /// The direction to move in, it is at most 1 long.
+    ///
+    /// Going diagonally is no faster than going straight, and a stick tilted only part of the way
+    /// gives a shorter vector. The deadzone is applied to the whole stick instead of each axis so
+    /// it does not snap to the axes.
+    pub fn movement(&self) -> Vec2 {
+        let movement = Vec2::new(
+            self.value(Action::MoveRight) - self.value(Action::MoveLeft),
+            self.value(Action::MoveUp) - self.value(Action::MoveDown),
+        );
+
+        let length = movement.length();
+        if length <= self.deadzone {
+            return Vec2::ZERO;
+        }
+
+        let strength = ((length - self.deadzone) / (1. - self.deadzone).max(f32::EPSILON)).min(1.);
+        movement / length * strength
+    }
+
+    /// Whether the action started this frame
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action) && !self.previously_pressed.contains(&action)
+    }
+
+    /// Whether the gamepad was used more recently than the keyboard and mouse
+    pub fn using_gamepad(&self) -> bool {
+        self.using_gamepad
+    }
+
+    /// The direction the right stick is pushed in, `None` while it rests in the deadzone
+    pub fn aim(&self) -> Option<Vec2> {
+        self.aim
+    }
+}
+
+fn update_action_state(
+    bindings: Res<InputBindings>,
+    keys: Res<Input<KeyCode>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    mut gamepad_events: EventReader<GamepadEvent>,
+    mut cursor_events: EventReader<CursorMoved>,
+    mut action_state: ResMut<ActionState>,
+) {
+    let action_state = &mut *action_state;
+
+    for GamepadEvent(gamepad, event) in gamepad_events.iter() {
+        match event {
+            GamepadEventType::Connected if action_state.gamepad.is_none() => {
+                info!("Using gamepad {:?}", gamepad);
+                action_state.gamepad = Some(*gamepad);
+            }
+            GamepadEventType::Disconnected if action_state.gamepad == Some(*gamepad) => {
+                info!("Gamepad {:?} has been disconnected", gamepad);
+                action_state.gamepad = None;
+                action_state.using_gamepad = false;
+            }
+            GamepadEventType::ButtonChanged(_, value) | GamepadEventType::AxisChanged(_, value)
+                if action_state.gamepad == Some(*gamepad) && value.abs() > bindings.deadzone =>
+            {
+                action_state.using_gamepad = true;
+            }
+            _ => (),
+        }
+    }
+
+    let gamepad = action_state.gamepad;
+    let axis = |axis_type| {
+        gamepad
+            .and_then(|gamepad| gamepad_axes.get(GamepadAxis(gamepad, axis_type)))
+            .unwrap_or(0.)
+    };
+
+    let digital = |pressed: bool| if pressed { 1. } else { 0. };
+    let value = |binding: &Binding| match *binding {
+        Binding::Key(key) => digital(keys.pressed(key)),
+        Binding::Mouse(button) => digital(mouse_buttons.pressed(button)),
+        Binding::GamepadButton(button) => digital(gamepad.map_or(false, |gamepad| {
+            gamepad_buttons.pressed(GamepadButton(gamepad, button))
+        })),
+        Binding::GamepadAxis(axis_type, AxisDirection::Positive) => axis(axis_type).max(0.),
+        Binding::GamepadAxis(axis_type, AxisDirection::Negative) => (-axis(axis_type)).max(0.),
+    };
+
+    std::mem::swap(
+        &mut action_state.pressed,
+        &mut action_state.previously_pressed,
+    );
+    action_state.pressed.clear();
+    action_state.values.clear();
+    action_state.deadzone = bindings.deadzone;
+
+    for (action, action_bindings) in &bindings.bindings {
+        let action_value = action_bindings
+            .iter()
+            .map(&value)
+            .fold(0., f32::max)
+            .min(1.);
+
+        if action_value > 0. {
+            action_state.values.insert(*action, action_value);
+        }
+        if action_value > bindings.deadzone {
+            action_state.pressed.insert(*action);
+        }
+    }
+
+    // All of them are read, unread ones would still count as movement next frame
+    let cursor_moved = cursor_events.iter().count() > 0;
+
+    // Touching the keyboard or mouse hands aiming back to the mouse
+    if keys.get_just_pressed().next().is_some()
+        || mouse_buttons.get_just_pressed().next().is_some()
+        || cursor_moved
+    {
+        action_state.using_gamepad = false;
+    }
+
+    let aim = Vec2::new(
+        axis(GamepadAxisType::RightStickX),
+        axis(GamepadAxisType::RightStickY),
+    );
+    action_state.aim = if aim.length() > bindings.deadzone {
+        Some(aim.normalize())
+    } else {
+        None
+    };
+}
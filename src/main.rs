@@ -11,12 +11,16 @@ pub const WINDOW_SCALE_FACTOR: f32 = 4.0;
 
 mod assets;
 mod bullets;
+mod combat;
+mod coords;
+mod input;
 mod map;
 mod misc;
 mod movement;
 mod physics;
 mod player;
 mod render;
+mod weapons;
 
 fn main() {
     let mut app = App::build();
@@ -31,13 +35,28 @@ fn main() {
     .add_plugin(bevy_ecs_tilemap::TilemapPlugin)
     .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
     .add_plugin(RapierRenderPlugin)
+    .add_plugin(input::ActionsPlugin)
+    // Other plugins add systems to the movement stage, so it has to exist first
+    .add_plugin(movement::MovementPlugin)
     .add_plugin(map::MapPlugin)
     .add_plugin(player::PlayerPlugin)
-    .add_plugin(movement::MovementPlugin)
     .add_plugin(assets::AssetsPlugin)
+    .add_plugin(weapons::WeaponsPlugin)
     .add_plugin(bullets::BulletsPlugin)
+    .add_plugin(combat::CombatPlugin)
     .add_plugin(physics::PhysicsPlugin);
 
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(seed) = std::env::var("ROOM_SEED")
+        .ok()
+        .and_then(|seed| seed.parse().ok())
+    {
+        app.insert_resource(map::RoomSource::Generated {
+            seed,
+            settings: Default::default(),
+        });
+    }
+
     #[cfg(target_arch = "wasm32")]
     {
         app.add_plugin(bevy_webgl2::WebGL2Plugin);
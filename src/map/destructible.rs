@@ -0,0 +1,66 @@
+use bevy::prelude::*;
+use bevy_ecs_tilemap::{MapQuery, Tile, TileParent};
+use bevy_rapier2d::physics::{ColliderBundle, RigidBodyBundle};
+
+use crate::combat::HitEvent;
+
+/// What destroyed tiles get replaced with
+pub const RUBBLE_TEXTURE: u16 = 3;
+/// The hit points of a crate if the room does not specify them
+pub const CRATE_HP: u32 = 3;
+
+/// A tile that gets turned into rubble once it has taken enough damage
+#[derive(Debug)]
+pub struct Destructible {
+    pub hp: u32,
+}
+
+pub(super) fn damage_destructibles(
+    mut commands: Commands,
+    mut map_query: MapQuery,
+    mut hit_events: EventReader<HitEvent>,
+    mut destructible_query: Query<(&mut Destructible, &UVec2, &TileParent)>,
+) {
+    for hit in hit_events.iter() {
+        let target = hit.target;
+
+        let (mut destructible, position, parent) =
+            if let Ok(destructible) = destructible_query.get_mut(target) {
+                destructible
+            } else {
+                continue;
+            };
+
+        // Another bullet already destroyed it this frame
+        if destructible.hp == 0 {
+            continue;
+        }
+
+        destructible.hp = destructible.hp.saturating_sub(hit.damage);
+        if destructible.hp > 0 {
+            continue;
+        }
+
+        if let Err(error) = map_query.set_tile(
+            &mut commands,
+            *position,
+            Tile {
+                texture_index: RUBBLE_TEXTURE,
+                ..Default::default()
+            },
+            parent.map_id,
+            parent.layer_id,
+        ) {
+            warn!("Could not turn tile at {} into rubble: {:?}", position, error);
+            continue;
+        }
+
+        commands
+            .entity(target)
+            .remove_bundle::<ColliderBundle>()
+            .remove_bundle::<RigidBodyBundle>()
+            .remove::<Destructible>();
+
+        map_query.notify_chunk_for_tile(*position, parent.map_id, parent.layer_id);
+    }
+}
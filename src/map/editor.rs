@@ -0,0 +1,331 @@
+use bevy::prelude::*;
+use bevy_ecs_tilemap::{MapQuery, Tile};
+use bevy_rapier2d::physics::{ColliderBundle, RigidBodyBundle};
+
+use super::{
+    CurrentRoom, Destructible, Room, RoomDescription, RoomTile, SpawnPoint, Wall, CRATE_HP,
+};
+use crate::{coords::MapGeometry, player::PlayerMouse};
+
+pub const TOGGLE_EDITOR_KEY: KeyCode = KeyCode::F2;
+pub const SAVE_ROOM_KEY: KeyCode = KeyCode::F5;
+
+/// Lets the current room be changed while playing it.
+///
+/// Left click paints the brush, right click toggles walls and `P` moves the player spawn. `C`
+/// toggles a crate and `O` a door, new doors get the next free id so a level can connect them.
+/// The brush is picked with `[` and `]`, the layer with `PageUp` and `PageDown`.
+#[derive(Default)]
+pub struct Editor {
+    pub enabled: bool,
+    pub brush: u16,
+    pub layer: u16,
+    /// The room as it has been edited so far, this is what gets saved
+    room: Option<Room>,
+}
+
+pub(super) fn toggle_editor(
+    keys: Res<Input<KeyCode>>,
+    mut editor: ResMut<Editor>,
+    rooms: Res<Assets<Room>>,
+    current_room: Res<CurrentRoom>,
+) {
+    // Edits are lost when the room changes underneath the editor
+    if current_room.is_changed() {
+        editor.room = None;
+    }
+
+    if !keys.just_pressed(TOGGLE_EDITOR_KEY) {
+        return;
+    }
+
+    editor.enabled = !editor.enabled;
+    info!(
+        "Editor {}",
+        if editor.enabled {
+            "enabled"
+        } else {
+            "disabled"
+        }
+    );
+
+    if editor.enabled && editor.room.is_none() {
+        editor.room = rooms.get(&current_room.0).cloned();
+        editor.layer = 0;
+    }
+}
+
+pub(super) fn select_brush(keys: Res<Input<KeyCode>>, mut editor: ResMut<Editor>) {
+    if !editor.enabled {
+        return;
+    }
+
+    let layer_count = editor
+        .room
+        .as_ref()
+        .map_or(1, |room| room.layers().len() as u16)
+        .max(1);
+
+    if keys.just_pressed(KeyCode::LBracket) {
+        editor.brush = editor.brush.saturating_sub(1);
+        info!("Brush: {}", editor.brush);
+    }
+    if keys.just_pressed(KeyCode::RBracket) {
+        editor.brush = editor.brush.saturating_add(1);
+        info!("Brush: {}", editor.brush);
+    }
+    if keys.just_pressed(KeyCode::PageUp) {
+        editor.layer = (editor.layer + 1) % layer_count;
+        info!("Layer: {}", editor.layer);
+    }
+    if keys.just_pressed(KeyCode::PageDown) {
+        editor.layer = (editor.layer + layer_count - 1) % layer_count;
+        info!("Layer: {}", editor.layer);
+    }
+}
+
+pub(super) fn edit_tiles(
+    mut commands: Commands,
+    mut map_query: MapQuery,
+    mut editor: ResMut<Editor>,
+    keys: Res<Input<KeyCode>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    map_geometry: Option<Res<MapGeometry>>,
+    mouse_query: Query<&PlayerMouse>,
+    spawn_query: Query<(Entity, &SpawnPoint)>,
+) {
+    if !editor.enabled {
+        return;
+    }
+
+    let map_geometry = if let Some(map_geometry) = map_geometry {
+        map_geometry
+    } else {
+        return;
+    };
+
+    let position = match mouse_query
+        .single()
+        .ok()
+        .and_then(|mouse| map_geometry.world_to_tile(mouse.position()))
+    {
+        Some(position) => position,
+        None => return,
+    };
+
+    let layer_id = editor.layer;
+    let brush = editor.brush;
+    let room = if let Some(room) = editor.room.as_mut() {
+        room
+    } else {
+        return;
+    };
+
+    let collidable = room
+        .layers()
+        .get(layer_id as usize)
+        .map_or(false, |layer| layer.collidable);
+    // Like `setup_map`, walls only block on collidable layers and crates have colliders of their own
+    let blocks = |tile: &RoomTile| {
+        tile.wall && collidable && tile.hp.is_none() && tile.spawn != Some(SpawnPoint::Crate)
+    };
+    let next_door = room
+        .layers()
+        .iter()
+        .flat_map(|layer| layer.iter())
+        .filter_map(|(_, tile)| match tile.spawn {
+            Some(SpawnPoint::Door(door)) => Some(door + 1),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
+
+    let place_spawn = keys.just_pressed(KeyCode::P);
+    if place_spawn {
+        // There is only ever one player spawn
+        for layer in room.layers_mut() {
+            let spawns: Vec<_> = layer
+                .iter()
+                .filter(|(_, tile)| tile.spawn == Some(SpawnPoint::Player))
+                .map(|(position, _)| position)
+                .collect();
+
+            for spawn in spawns {
+                layer.get_mut(spawn).unwrap().spawn = None;
+            }
+        }
+
+        for (entity, spawn) in spawn_query.iter() {
+            if *spawn == SpawnPoint::Player {
+                commands.entity(entity).remove::<SpawnPoint>();
+            }
+        }
+    }
+
+    let room_tile = match room
+        .layers_mut()
+        .get_mut(layer_id as usize)
+        .and_then(|layer| layer.get_or_insert(position))
+    {
+        Some(room_tile) => room_tile,
+        None => return,
+    };
+
+    if mouse_buttons.pressed(MouseButton::Left) && room_tile.texture_index != brush {
+        room_tile.texture_index = brush;
+
+        match map_query.set_tile(
+            &mut commands,
+            position,
+            Tile {
+                texture_index: brush,
+                ..Default::default()
+            },
+            0u16,
+            layer_id,
+        ) {
+            Ok(entity) => {
+                if blocks(room_tile) {
+                    commands.entity(entity).insert(Wall);
+                }
+                if let Some(spawn) = room_tile.spawn {
+                    commands.entity(entity).insert(spawn);
+                }
+            }
+            Err(error) => warn!("Could not paint tile at {}: {:?}", position, error),
+        }
+
+        map_query.notify_chunk_for_tile(position, 0u16, layer_id);
+    }
+
+    let tile_entity = map_query.get_tile_entity(position, 0u16, layer_id);
+
+    if mouse_buttons.just_pressed(MouseButton::Right) {
+        room_tile.wall = !room_tile.wall;
+
+        if let Ok(entity) = tile_entity {
+            if blocks(room_tile) {
+                commands.entity(entity).insert(Wall);
+            } else {
+                commands.entity(entity).remove::<Wall>();
+            }
+        }
+    }
+
+    if place_spawn {
+        room_tile.spawn = Some(SpawnPoint::Player);
+
+        if let Ok(entity) = tile_entity {
+            commands.entity(entity).insert(SpawnPoint::Player);
+        }
+    }
+
+    if keys.just_pressed(KeyCode::C) {
+        if room_tile.spawn == Some(SpawnPoint::Crate) {
+            room_tile.spawn = None;
+            room_tile.wall = false;
+
+            if let Ok(entity) = tile_entity {
+                commands
+                    .entity(entity)
+                    .remove::<SpawnPoint>()
+                    .remove::<Destructible>()
+                    .remove_bundle::<ColliderBundle>()
+                    .remove_bundle::<RigidBodyBundle>();
+            }
+        } else {
+            // Crates block the way like walls until they get shot
+            room_tile.spawn = Some(SpawnPoint::Crate);
+            room_tile.wall = true;
+
+            if let Ok(entity) = tile_entity {
+                commands.entity(entity).insert(SpawnPoint::Crate);
+
+                if collidable {
+                    commands.entity(entity).insert(Destructible {
+                        hp: room_tile.hp.unwrap_or(CRATE_HP),
+                    });
+                }
+            }
+        }
+    }
+
+    if keys.just_pressed(KeyCode::O) {
+        if let Some(SpawnPoint::Door(door)) = room_tile.spawn {
+            info!("Removed door {}", door);
+            room_tile.spawn = None;
+
+            if let Ok(entity) = tile_entity {
+                commands.entity(entity).remove::<SpawnPoint>();
+            }
+        } else {
+            info!("Placed door {}", next_door);
+            // The player has to be able to walk onto a door to use it, so it replaces walls and crates
+            room_tile.spawn = Some(SpawnPoint::Door(next_door));
+            room_tile.wall = false;
+            room_tile.hp = None;
+
+            if let Ok(entity) = tile_entity {
+                commands
+                    .entity(entity)
+                    .insert(SpawnPoint::Door(next_door))
+                    .remove::<Wall>()
+                    .remove::<Destructible>()
+                    .remove_bundle::<ColliderBundle>()
+                    .remove_bundle::<RigidBodyBundle>();
+            }
+        }
+    }
+}
+
+pub(super) fn save_room(
+    keys: Res<Input<KeyCode>>,
+    editor: Res<Editor>,
+    asset_server: Res<AssetServer>,
+    current_room: Res<CurrentRoom>,
+) {
+    if !editor.enabled || !keys.just_pressed(SAVE_ROOM_KEY) {
+        return;
+    }
+
+    let room = if let Some(room) = &editor.room {
+        room
+    } else {
+        return;
+    };
+
+    // Generated rooms have no file, they get saved so they can be turned into a handmade one
+    let path = asset_server.get_handle_path(&current_room.0).map_or_else(
+        || "generated.room".into(),
+        |path| path.path().with_extension("room"),
+    );
+
+    let description = match RoomDescription::from_room(room) {
+        Ok(description) => description,
+        Err(error) => {
+            warn!("Could not save room: {}", error);
+            return;
+        }
+    };
+
+    write_room(&path, &description);
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_room(path: &std::path::Path, description: &RoomDescription) {
+    let path = std::path::Path::new("assets").join(path);
+
+    let result = ron::ser::to_string_pretty(description, Default::default())
+        .map_err(anyhow::Error::from)
+        .and_then(|room| std::fs::write(&path, room).map_err(anyhow::Error::from));
+
+    match result {
+        Ok(()) => info!("Saved room to {}", path.display()),
+        Err(error) => warn!("Could not save room to {}: {}", path.display(), error),
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn write_room(path: &std::path::Path, _description: &RoomDescription) {
+    warn!("Cannot save {} in the browser", path.display());
+}
@@ -0,0 +1,316 @@
+use std::{collections::VecDeque, ops::Range};
+
+use bevy::prelude::*;
+
+use super::room::{LayerKind, Room, RoomLayer, RoomSettings, RoomTile, SpawnPoint};
+
+pub const FLOOR_TEXTURE: u16 = 0;
+pub const WALL_TEXTURE: u16 = 1;
+pub const CRATE_TEXTURE: u16 = 2;
+
+/// A SplitMix64 generator, it is tiny and gives the same numbers on every platform
+#[derive(Debug, Clone)]
+pub struct RoomRng {
+    state: u64,
+}
+
+impl RoomRng {
+    pub fn new(seed: u64) -> Self {
+        RoomRng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number in the given range, the range may not be empty
+    pub fn range(&mut self, range: Range<u32>) -> u32 {
+        assert!(range.start < range.end, "Cannot pick from an empty range");
+        range.start + (self.next_u64() % u64::from(range.end - range.start)) as u32
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GeneratorSettings {
+    pub size: UVec2,
+    /// How many rows of shelves are attempted, rows that would block the room are left out
+    pub shelf_rows: u32,
+    pub crate_clusters: u32,
+    /// Openings in the outer wall, at most one per side
+    pub doorways: u32,
+}
+
+impl Default for GeneratorSettings {
+    fn default() -> Self {
+        GeneratorSettings {
+            size: UVec2::new(16, 16),
+            shelf_rows: 3,
+            crate_clusters: 4,
+            doorways: 2,
+        }
+    }
+}
+
+fn floor() -> RoomTile {
+    RoomTile {
+        texture_index: FLOOR_TEXTURE,
+        ..Default::default()
+    }
+}
+
+fn wall() -> RoomTile {
+    RoomTile {
+        texture_index: WALL_TEXTURE,
+        wall: true,
+        ..Default::default()
+    }
+}
+
+fn crate_tile() -> RoomTile {
+    RoomTile {
+        texture_index: CRATE_TEXTURE,
+        wall: true,
+        spawn: Some(SpawnPoint::Crate),
+        ..Default::default()
+    }
+}
+
+fn is_walkable(layer: &RoomLayer, position: UVec2) -> bool {
+    layer.get(position).map_or(false, |tile| !tile.wall)
+}
+
+/// Checks that every walkable tile can be reached from `start`
+fn is_connected(layer: &RoomLayer, size: UVec2, start: UVec2) -> bool {
+    let mut visited = vec![false; (size.x * size.y) as usize];
+    let mut queue = VecDeque::new();
+    let mut reached = 0;
+
+    if is_walkable(layer, start) {
+        visited[(start.y * size.x + start.x) as usize] = true;
+        queue.push_back(start);
+    }
+
+    while let Some(position) = queue.pop_front() {
+        reached += 1;
+
+        let neighbours = [
+            position.x.checked_sub(1).map(|x| UVec2::new(x, position.y)),
+            position.y.checked_sub(1).map(|y| UVec2::new(position.x, y)),
+            Some(UVec2::new(position.x + 1, position.y)),
+            Some(UVec2::new(position.x, position.y + 1)),
+        ];
+
+        for neighbour in neighbours.iter().flatten().copied() {
+            if neighbour.x >= size.x || neighbour.y >= size.y {
+                continue;
+            }
+
+            let index = (neighbour.y * size.x + neighbour.x) as usize;
+            if !visited[index] && is_walkable(layer, neighbour) {
+                visited[index] = true;
+                queue.push_back(neighbour);
+            }
+        }
+    }
+
+    reached == layer.iter().filter(|(_, tile)| !tile.wall).count()
+}
+
+/// Places all tiles or none of them, depending on whether the room stays connected
+fn place_obstacle(
+    layer: &mut RoomLayer,
+    size: UVec2,
+    spawn: UVec2,
+    positions: &[UVec2],
+    tile: &RoomTile,
+) {
+    if positions
+        .iter()
+        .any(|position| *position == spawn || !is_walkable(layer, *position))
+    {
+        return;
+    }
+
+    for position in positions {
+        layer.set(*position, Some(tile.clone()));
+    }
+
+    if !is_connected(layer, size, spawn) {
+        for position in positions {
+            layer.set(*position, Some(floor()));
+        }
+    }
+}
+
+/// Generates a storage room, the same seed and settings always result in the same room
+pub fn generate_room(seed: u64, settings: &GeneratorSettings) -> Room {
+    let size = settings.size.max(UVec2::new(5, 5));
+    let mut rng = RoomRng::new(seed);
+    let mut layer = RoomLayer::filled(size, LayerKind::Floor, floor());
+
+    for x in 0..size.x {
+        layer.set(UVec2::new(x, 0), Some(wall()));
+        layer.set(UVec2::new(x, size.y - 1), Some(wall()));
+    }
+    for y in 0..size.y {
+        layer.set(UVec2::new(0, y), Some(wall()));
+        layer.set(UVec2::new(size.x - 1, y), Some(wall()));
+    }
+
+    let mut sides = vec![0, 1, 2, 3];
+    for door_id in 0..settings.doorways.min(4) {
+        let side = sides.remove(rng.range(0..sides.len() as u32) as usize);
+        let door = match side {
+            0 => UVec2::new(rng.range(1..size.x - 1), 0),
+            1 => UVec2::new(rng.range(1..size.x - 1), size.y - 1),
+            2 => UVec2::new(0, rng.range(1..size.y - 1)),
+            _ => UVec2::new(size.x - 1, rng.range(1..size.y - 1)),
+        };
+        layer.set(
+            door,
+            Some(RoomTile {
+                spawn: Some(SpawnPoint::Door(door_id)),
+                ..floor()
+            }),
+        );
+    }
+
+    let spawn = UVec2::new(rng.range(1..size.x - 1), rng.range(1..size.y - 1));
+
+    // Shelves run horizontally with an aisle of two tiles between them
+    let mut shelf_y = 3;
+    for _ in 0..settings.shelf_rows {
+        if shelf_y + 2 >= size.y || size.x < 7 {
+            break;
+        }
+
+        let mut x = 2;
+        while x < size.x - 2 {
+            let length = rng.range(2..6).min(size.x - 2 - x);
+            let shelf: Vec<_> = (x..x + length).map(|x| UVec2::new(x, shelf_y)).collect();
+            place_obstacle(&mut layer, size, spawn, &shelf, &wall());
+
+            // Leave a gap so the aisles are connected
+            x += length + rng.range(1..3);
+        }
+
+        shelf_y += 3;
+    }
+
+    for _ in 0..settings.crate_clusters {
+        let center = UVec2::new(rng.range(1..size.x - 1), rng.range(1..size.y - 1));
+        let count = rng.range(1..5);
+
+        for _ in 0..count {
+            let offset_x = rng.range(0..3) as i32 - 1;
+            let offset_y = rng.range(0..3) as i32 - 1;
+            let position = UVec2::new(
+                (center.x as i32 + offset_x).clamp(1, size.x as i32 - 2) as u32,
+                (center.y as i32 + offset_y).clamp(1, size.y as i32 - 2) as u32,
+            );
+
+            place_obstacle(&mut layer, size, spawn, &[position], &crate_tile());
+        }
+    }
+
+    if let Some(tile) = layer.get_mut(spawn) {
+        tile.spawn = Some(SpawnPoint::Player);
+    }
+
+    let mut room = Room::new(size, RoomSettings::default());
+    room.add_layer(layer);
+    room
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every tile of every layer, `Room` itself cannot be compared
+    fn tiles(room: &Room) -> Vec<(LayerKind, Vec<(UVec2, RoomTile)>)> {
+        room.layers()
+            .iter()
+            .map(|layer| {
+                let tiles = layer
+                    .iter()
+                    .map(|(position, tile)| (position, tile.clone()))
+                    .collect();
+                (layer.kind, tiles)
+            })
+            .collect()
+    }
+
+    fn spawn_points(room: &Room) -> Vec<(UVec2, SpawnPoint)> {
+        room.layers()[0]
+            .iter()
+            .filter_map(|(position, tile)| tile.spawn.map(|spawn| (position, spawn)))
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_gives_same_room() {
+        let settings = GeneratorSettings::default();
+
+        for seed in 0..20 {
+            let first = generate_room(seed, &settings);
+            let second = generate_room(seed, &settings);
+
+            assert_eq!(first.size(), second.size());
+            assert_eq!(tiles(&first), tiles(&second), "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn different_seeds_give_different_rooms() {
+        let settings = GeneratorSettings::default();
+        let rooms: Vec<_> = (0..20)
+            .map(|seed| tiles(&generate_room(seed, &settings)))
+            .collect();
+
+        for (index, room) in rooms.iter().enumerate() {
+            assert!(
+                rooms[index + 1..].iter().all(|other| other != room),
+                "seed {} gives the same room as a later seed",
+                index
+            );
+        }
+    }
+
+    #[test]
+    fn doors_and_spawn_are_reachable() {
+        let settings = GeneratorSettings {
+            size: UVec2::new(20, 14),
+            shelf_rows: 4,
+            crate_clusters: 8,
+            doorways: 4,
+        };
+
+        for seed in 0..50 {
+            let room = generate_room(seed, &settings);
+            let layer = &room.layers()[0];
+            let spawn_points = spawn_points(&room);
+
+            let spawn = spawn_points
+                .iter()
+                .find(|(_, spawn)| *spawn == SpawnPoint::Player)
+                .map(|(position, _)| *position)
+                .expect("Every room has a player spawn");
+
+            let doors: Vec<_> = spawn_points
+                .iter()
+                .filter(|(_, spawn)| matches!(spawn, SpawnPoint::Door(_)))
+                .collect();
+            assert_eq!(doors.len(), 4, "seed {}", seed);
+
+            assert!(is_connected(layer, room.size(), spawn), "seed {}", seed);
+            for (door, _) in doors {
+                assert!(is_walkable(layer, *door), "seed {}", seed);
+                assert!(is_connected(layer, room.size(), *door), "seed {}", seed);
+            }
+        }
+    }
+}
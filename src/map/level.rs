@@ -0,0 +1,230 @@
+use std::path::PathBuf;
+
+use anyhow::bail;
+use bevy::{
+    asset::{AssetLoader, AssetPath, LoadContext, LoadedAsset},
+    math::Vec3Swizzles,
+    prelude::*,
+    reflect::TypeUuid,
+    utils::{BoxedFuture, HashMap, HashSet},
+};
+use serde::Deserialize;
+
+use super::{room::Room, CurrentRoom, SpawnPoint};
+use crate::{coords::MapGeometry, player::Player};
+
+/// Identifies a door inside of a level, the index of the room and the id of the door in that room
+pub type DoorId = (usize, u32);
+
+/// A set of rooms connected through their doors
+#[derive(Debug, TypeUuid)]
+#[uuid = "5d1c8e0a-7b6f-4f2e-9c3a-d84e2f71b6a0"]
+pub struct Level {
+    pub start: usize,
+    pub rooms: Vec<Handle<Room>>,
+    doors: HashMap<DoorId, DoorId>,
+}
+
+impl Level {
+    /// The door on the other side, doors always lead both ways
+    pub fn destination(&self, door: DoorId) -> Option<DoorId> {
+        self.doors.get(&door).copied()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct LevelDescription {
+    start: usize,
+    rooms: Vec<String>,
+    doors: Vec<(DoorId, DoorId)>,
+}
+
+#[derive(Default)]
+pub struct LevelLoader;
+
+impl AssetLoader for LevelLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let description: LevelDescription = ron::de::from_bytes(bytes)?;
+
+            if description.start >= description.rooms.len() {
+                bail!("Start room {} does not exist", description.start);
+            }
+
+            let mut doors = HashMap::default();
+            for (from, to) in description.doors {
+                if from.0 >= description.rooms.len() || to.0 >= description.rooms.len() {
+                    bail!(
+                        "Door {:?} <-> {:?} leads to a room that does not exist",
+                        from,
+                        to
+                    );
+                }
+
+                doors.insert(from, to);
+                doors.insert(to, from);
+            }
+
+            let rooms = description
+                .rooms
+                .iter()
+                .map(|path| load_context.get_handle(path.as_str()))
+                .collect();
+
+            let dependencies = description
+                .rooms
+                .iter()
+                .map(|path| AssetPath::new(PathBuf::from(path), None))
+                .collect();
+
+            load_context.set_default_asset(
+                LoadedAsset::new(Level {
+                    start: description.start,
+                    rooms,
+                    doors,
+                })
+                .with_dependencies(dependencies),
+            );
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level"]
+    }
+}
+
+/// The level being played and the room of it the player is in
+pub struct CurrentLevel {
+    pub level: Handle<Level>,
+    pub room: usize,
+}
+
+/// Where the player gets placed when the next room is built
+pub struct PlayerArrival(pub SpawnPoint);
+
+impl Default for PlayerArrival {
+    fn default() -> Self {
+        PlayerArrival(SpawnPoint::Player)
+    }
+}
+
+/// Keeps track of what the player has done in the level, it lives as long as the level does
+#[derive(Default, Debug)]
+pub struct Progression {
+    pub visited_rooms: HashSet<usize>,
+    pub doors_used: u32,
+}
+
+/// A door only leads somewhere once the player has stepped off a door, so arriving on one does not
+/// immediately send them back
+#[derive(Default)]
+pub(super) struct DoorTraversal {
+    armed: bool,
+    /// Set while the next room is being built, until then the player still stands where they left
+    /// the previous room
+    arriving: bool,
+}
+
+pub(super) fn enter_level(
+    mut level_events: EventReader<AssetEvent<Level>>,
+    levels: Res<Assets<Level>>,
+    current_level: Option<ResMut<CurrentLevel>>,
+    mut current_room: ResMut<CurrentRoom>,
+    mut progression: ResMut<Progression>,
+) {
+    let mut current_level = if let Some(current_level) = current_level {
+        current_level
+    } else {
+        return;
+    };
+
+    for event in level_events.iter() {
+        if let AssetEvent::Created { handle } = event {
+            if *handle != current_level.level {
+                continue;
+            }
+
+            let level = levels.get(handle).unwrap();
+            current_level.room = level.start;
+            current_room.0 = level.rooms[level.start].clone();
+            progression.visited_rooms.insert(level.start);
+        }
+    }
+}
+
+pub(super) fn use_doors(
+    player_query: Query<&Transform, With<Player>>,
+    door_query: Query<(&UVec2, &SpawnPoint)>,
+    new_spawn_query: Query<&SpawnPoint, Added<SpawnPoint>>,
+    map_geometry: Option<Res<MapGeometry>>,
+    levels: Res<Assets<Level>>,
+    current_level: Option<ResMut<CurrentLevel>>,
+    mut current_room: ResMut<CurrentRoom>,
+    mut arrival: ResMut<PlayerArrival>,
+    mut progression: ResMut<Progression>,
+    mut traversal: ResMut<DoorTraversal>,
+) {
+    let (map_geometry, mut current_level) = match (map_geometry, current_level) {
+        (Some(map_geometry), Some(current_level)) => (map_geometry, current_level),
+        _ => return,
+    };
+
+    let level = if let Some(level) = levels.get(&current_level.level) {
+        level
+    } else {
+        return;
+    };
+
+    // The player gets placed on the arrival door in the same frame its tile shows up
+    if traversal.arriving {
+        if !new_spawn_query.iter().any(|spawn| *spawn == arrival.0) {
+            return;
+        }
+        traversal.arriving = false;
+    }
+
+    for transform in player_query.iter() {
+        let tile = map_geometry.world_to_tile(transform.translation.xy());
+
+        let door = door_query.iter().find_map(|(position, spawn)| match spawn {
+            SpawnPoint::Door(door) if Some(*position) == tile => Some(*door),
+            _ => None,
+        });
+
+        let door = if let Some(door) = door {
+            door
+        } else {
+            traversal.armed = true;
+            continue;
+        };
+
+        if !traversal.armed {
+            continue;
+        }
+
+        let (room, arrival_door) =
+            if let Some(destination) = level.destination((current_level.room, door)) {
+                destination
+            } else {
+                continue;
+            };
+
+        info!(
+            "Going through door {} of room {} into room {}",
+            door, current_level.room, room
+        );
+
+        traversal.armed = false;
+        traversal.arriving = true;
+        current_level.room = room;
+        current_room.0 = level.rooms[room].clone();
+        arrival.0 = SpawnPoint::Door(arrival_door);
+        progression.visited_rooms.insert(room);
+        progression.doors_used += 1;
+    }
+}
@@ -0,0 +1,263 @@
+use bevy::prelude::*;
+use bevy_ecs_tilemap::{GPUAnimated, LayerBuilder, Map, MapQuery, Tile, TileBundle};
+use bevy_rapier2d::{
+    physics::{ColliderBundle, RigidBodyBundle},
+    prelude::{ColliderShape, ColliderType, RigidBodyType},
+};
+
+use crate::{
+    combat::HitDetection,
+    coords::MapGeometry,
+    movement::{MovementCalculation, MovementStage},
+    player::PlayerPlacement,
+};
+
+mod destructible;
+mod editor;
+mod generator;
+mod level;
+mod room;
+mod surface;
+mod tiled;
+
+pub use destructible::{Destructible, CRATE_HP, RUBBLE_TEXTURE};
+pub use editor::Editor;
+pub use generator::{generate_room, GeneratorSettings};
+pub use level::{CurrentLevel, Level, LevelLoader, PlayerArrival, Progression};
+pub use room::{
+    LayerDescription, LayerKind, Room, RoomDescription, RoomLayer, RoomLoader, RoomSettings,
+    RoomTile, SpawnPoint, Surface, TileAnimation,
+};
+pub use tiled::TiledLoader;
+
+pub const TILE_SIZE: f32 = 16.;
+
+pub struct MapPlugin;
+
+impl Plugin for MapPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_asset::<Room>();
+        app.init_asset_loader::<RoomLoader>();
+        app.init_asset_loader::<TiledLoader>();
+        app.add_asset::<Level>();
+        app.init_asset_loader::<LevelLoader>();
+        app.init_resource::<PlayerArrival>();
+        app.init_resource::<Progression>();
+        app.init_resource::<level::DoorTraversal>();
+        app.add_startup_system(start_game.system());
+        app.add_system(level::enter_level.system());
+        app.add_system(level::use_doors.system().after(PlayerPlacement));
+        app.add_system(setup_map.system());
+        app.add_system(destructible::damage_destructibles.system().after(HitDetection));
+        app.add_system_to_stage(
+            MovementStage,
+            surface::apply_surfaces
+                .system()
+                .label(MovementCalculation::Input)
+                .before(MovementCalculation::Movements),
+        );
+        app.init_resource::<Editor>();
+        app.add_system(editor::toggle_editor.system());
+        app.add_system(editor::select_brush.system());
+        app.add_system(editor::edit_tiles.system());
+        app.add_system(editor::save_room.system());
+    }
+}
+
+/// The room that is currently built into the tilemap
+pub struct CurrentRoom(pub Handle<Room>);
+
+/// Where the first room comes from, inserted before the `MapPlugin` to override the default level
+pub enum RoomSource {
+    Level(String),
+    File(String),
+    Generated {
+        seed: u64,
+        settings: GeneratorSettings,
+    },
+}
+
+impl Default for RoomSource {
+    fn default() -> Self {
+        RoomSource::Level("storage.level".into())
+    }
+}
+
+/// The material all room layers are drawn with
+pub struct MapMaterial(pub Handle<ColorMaterial>);
+
+fn start_game(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut rooms: ResMut<Assets<Room>>,
+    room_source: Option<Res<RoomSource>>,
+) {
+    let texture_handle = asset_server.load("atlas.png");
+    let material_handle = materials.add(ColorMaterial::texture(texture_handle));
+
+    commands.insert_resource(MapMaterial(material_handle));
+
+    let default_source = RoomSource::default();
+    let room = match room_source.as_deref().unwrap_or(&default_source) {
+        RoomSource::Level(path) => {
+            commands.insert_resource(CurrentLevel {
+                level: asset_server.load(path.as_str()),
+                room: 0,
+            });
+            // The room is picked once the level has been loaded
+            Handle::default()
+        }
+        RoomSource::Generated { seed, settings } => {
+            info!("Generating room with seed {}", seed);
+            rooms.add(generate_room(*seed, settings))
+        }
+        RoomSource::File(path) => asset_server.load(path.as_str()),
+    };
+
+    commands.insert_resource(CurrentRoom(room));
+}
+
+pub struct Wall;
+
+fn setup_map(
+    mut commands: Commands,
+    mut map_query: MapQuery,
+    mut room_events: EventReader<AssetEvent<Room>>,
+    rooms: Res<Assets<Room>>,
+    current_room: Res<CurrentRoom>,
+    material: Res<MapMaterial>,
+    existing_maps: Query<(), With<Map>>,
+) {
+    let room_loaded = room_events.iter().any(|event| match event {
+        AssetEvent::Created { handle } | AssetEvent::Modified { handle } => {
+            *handle == current_room.0
+        }
+        AssetEvent::Removed { .. } => false,
+    });
+
+    // Rooms of a level are loaded up front, so switching rooms does not send an asset event
+    let room_changed = room_loaded || current_room.is_changed();
+
+    if !room_changed {
+        return;
+    }
+
+    let room = if let Some(room) = rooms.get(&current_room.0) {
+        room
+    } else {
+        return;
+    };
+
+    if existing_maps.iter().next().is_some() {
+        map_query.despawn(&mut commands, 0u16);
+    }
+
+    let map_entity = commands.spawn().id();
+    let mut map = Map::new(0u16, map_entity);
+
+    let settings = room.layer_settings();
+    let map_center = settings.get_pixel_center();
+
+    // The tilemap draws layers in the order of their ids
+    let actor_depth = room
+        .layers()
+        .iter()
+        .rposition(|room_layer| room_layer.kind != LayerKind::Overhead)
+        .map_or(0., |layer_id| layer_id as f32)
+        + 0.5;
+
+    for (layer_id, room_layer) in room.layers().iter().enumerate() {
+        let layer_id = layer_id as u16;
+
+        #[cfg(target_arch = "wasm32")]
+        let pipeline = Some(RenderPipelines::from_pipelines(vec![
+            bevy::render::pipeline::RenderPipeline::new(crate::render::SQUARE_PIPELINE.typed()),
+        ]));
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let pipeline = None;
+
+        let (mut layer_builder, _) =
+            LayerBuilder::new(&mut commands, settings, 0u16, layer_id, pipeline);
+
+        for (position, room_tile) in room_layer.iter() {
+            if let Err(error) = layer_builder.set_tile(
+                position,
+                TileBundle {
+                    tile: Tile {
+                        texture_index: room_tile.texture_index,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            ) {
+                warn!("Could not place room tile at {}: {:?}", position, error);
+                continue;
+            }
+
+            let hp = match (room_tile.hp, room_tile.spawn) {
+                _ if !room_layer.collidable => None,
+                (Some(hp), _) => Some(hp),
+                (None, Some(SpawnPoint::Crate)) => Some(CRATE_HP),
+                (None, _) => None,
+            };
+            let wall = room_tile.wall && room_layer.collidable;
+
+            if wall
+                || room_tile.spawn.is_some()
+                || hp.is_some()
+                || room_tile.animation.is_some()
+                || room_tile.surface.is_some()
+            {
+                let entity = layer_builder
+                    .get_tile_entity(&mut commands, position)
+                    .unwrap();
+
+                if let Some(hp) = hp {
+                    commands.entity(entity).insert(Destructible { hp });
+                }
+
+                // Destructible tiles have a collider of their own, only the others get merged
+                if wall && hp.is_none() {
+                    commands.entity(entity).insert(Wall);
+                }
+
+                if let Some(spawn) = room_tile.spawn {
+                    commands.entity(entity).insert(spawn);
+                }
+
+                if let Some(animation) = room_tile.animation {
+                    commands.entity(entity).insert(GPUAnimated::new(
+                        animation.start,
+                        animation.end,
+                        animation.speed,
+                    ));
+                }
+
+                if let Some(surface) = room_tile.surface {
+                    commands.entity(entity).insert(surface);
+                }
+            }
+        }
+
+        let layer_entity = map_query.build_layer(&mut commands, layer_builder, material.0.clone());
+
+        map.add_layer(&mut commands, layer_id, layer_entity);
+    }
+
+    commands
+        .entity(map_entity)
+        .insert(map)
+        .insert(Transform::from_xyz(-map_center.x, -map_center.y, 0.))
+        .insert(GlobalTransform::default());
+
+    commands.insert_resource(MapGeometry {
+        origin: -map_center,
+        tile_size: room.settings().tile_size,
+        size: room.size(),
+        actor_depth,
+    });
+
+    info!("Setup map!");
+}
@@ -0,0 +1,422 @@
+use std::collections::BTreeMap;
+
+use anyhow::bail;
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use bevy_ecs_tilemap::LayerSettings;
+use serde::{Deserialize, Serialize};
+
+/// Marks a tile as the place where something gets spawned when the room is built.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SpawnPoint {
+    Player,
+    Crate,
+    /// A door with an id that is unique inside of its room, see `Level` for where it leads
+    Door(u32),
+}
+
+/// Cycles the tile through the textures from `start` up to, but not including, `end`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TileAnimation {
+    pub start: u32,
+    pub end: u32,
+    /// How many times per second the animation loops
+    pub speed: f32,
+}
+
+/// Changes how everything standing on the tile moves, e.g. oil slicks, sticky floors and conveyors
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Surface {
+    /// Scales how quickly movements die down, below 1 is slippery and above 1 sticky
+    #[serde(default = "Surface::no_change")]
+    pub friction: f32,
+    /// Scales how fast movements can get
+    #[serde(default = "Surface::no_change")]
+    pub speed: f32,
+    /// A constant push in pixels per second
+    #[serde(default)]
+    pub push: [f32; 2],
+}
+
+impl Surface {
+    fn no_change() -> f32 {
+        1.
+    }
+}
+
+impl Default for Surface {
+    fn default() -> Self {
+        Surface {
+            friction: 1.,
+            speed: 1.,
+            push: [0., 0.],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RoomTile {
+    pub texture_index: u16,
+    #[serde(default)]
+    pub wall: bool,
+    #[serde(default)]
+    pub spawn: Option<SpawnPoint>,
+    /// Tiles with hit points can be shot apart, crates always can be
+    #[serde(default)]
+    pub hp: Option<u32>,
+    #[serde(default)]
+    pub animation: Option<TileAnimation>,
+    #[serde(default)]
+    pub surface: Option<Surface>,
+}
+
+/// How the tiles of a room are laid out in chunks and in the texture atlas.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RoomSettings {
+    pub chunk_size: UVec2,
+    pub tile_size: Vec2,
+    pub texture_size: Vec2,
+}
+
+impl Default for RoomSettings {
+    fn default() -> Self {
+        RoomSettings {
+            chunk_size: UVec2::new(8, 8),
+            tile_size: Vec2::new(super::TILE_SIZE, super::TILE_SIZE),
+            texture_size: Vec2::new(256., 256.),
+        }
+    }
+}
+
+/// A single storage room, made up of one or more layers drawn on top of each other.
+#[derive(Debug, Clone, TypeUuid)]
+#[uuid = "9a3f2c71-4e0b-4c55-8f3e-2b7d61c0a5e4"]
+pub struct Room {
+    size: UVec2,
+    settings: RoomSettings,
+    layers: Vec<RoomLayer>,
+}
+
+impl Room {
+    pub fn new(size: UVec2, settings: RoomSettings) -> Self {
+        Room {
+            size,
+            settings,
+            layers: vec![],
+        }
+    }
+
+    pub fn size(&self) -> UVec2 {
+        self.size
+    }
+
+    pub fn settings(&self) -> RoomSettings {
+        self.settings
+    }
+
+    /// The layers in the order they are drawn, their index is the id of their tilemap layer
+    pub fn layers(&self) -> &[RoomLayer] {
+        &self.layers
+    }
+
+    pub fn layers_mut(&mut self) -> &mut [RoomLayer] {
+        &mut self.layers
+    }
+
+    /// Adds the layer above all layers of the same or a lower kind, returns its index
+    pub fn add_layer(&mut self, layer: RoomLayer) -> usize {
+        let index = self
+            .layers
+            .iter()
+            .rposition(|existing| existing.kind <= layer.kind)
+            .map_or(0, |index| index + 1);
+        self.layers.insert(index, layer);
+        index
+    }
+
+    /// The settings for each tilemap layer, the map is made large enough to fit the whole room.
+    pub fn layer_settings(&self) -> LayerSettings {
+        let chunk_size = self.settings.chunk_size;
+        let map_size = UVec2::new(
+            (self.size.x + chunk_size.x - 1) / chunk_size.x,
+            (self.size.y + chunk_size.y - 1) / chunk_size.y,
+        );
+
+        LayerSettings::new(
+            map_size,
+            chunk_size,
+            self.settings.tile_size,
+            self.settings.texture_size,
+        )
+    }
+}
+
+/// Layers are drawn in the order of their kind, actors walk above decorations and below overhead
+/// layers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum LayerKind {
+    Floor,
+    Decoration,
+    Overhead,
+}
+
+impl LayerKind {
+    /// Overhead layers are out of reach, everything else blocks movement with its walls
+    pub fn is_collidable_by_default(self) -> bool {
+        self != LayerKind::Overhead
+    }
+}
+
+/// The tiles of a single layer, stored row by row with `(0, 0)` being the bottom left tile.
+#[derive(Debug, Clone)]
+pub struct RoomLayer {
+    pub kind: LayerKind,
+    /// Only walls on collidable layers get colliders
+    pub collidable: bool,
+    size: UVec2,
+    tiles: Vec<Option<RoomTile>>,
+}
+
+impl RoomLayer {
+    pub fn new(size: UVec2, kind: LayerKind) -> Self {
+        RoomLayer {
+            kind,
+            collidable: kind.is_collidable_by_default(),
+            size,
+            tiles: vec![None; (size.x * size.y) as usize],
+        }
+    }
+
+    pub fn filled(size: UVec2, kind: LayerKind, fill: RoomTile) -> Self {
+        RoomLayer {
+            tiles: vec![Some(fill); (size.x * size.y) as usize],
+            ..RoomLayer::new(size, kind)
+        }
+    }
+
+    fn index(&self, position: UVec2) -> Option<usize> {
+        if position.x < self.size.x && position.y < self.size.y {
+            Some((position.y * self.size.x + position.x) as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn get(&self, position: UVec2) -> Option<&RoomTile> {
+        self.index(position)
+            .and_then(|index| self.tiles[index].as_ref())
+    }
+
+    pub fn get_mut(&mut self, position: UVec2) -> Option<&mut RoomTile> {
+        let index = self.index(position)?;
+        self.tiles[index].as_mut()
+    }
+
+    /// Returns the tile at the given position, placing a default tile if the position is empty.
+    pub fn get_or_insert(&mut self, position: UVec2) -> Option<&mut RoomTile> {
+        let index = self.index(position)?;
+        Some(self.tiles[index].get_or_insert_with(Default::default))
+    }
+
+    pub fn set(&mut self, position: UVec2, tile: Option<RoomTile>) {
+        if let Some(index) = self.index(position) {
+            self.tiles[index] = tile;
+        }
+    }
+
+    /// Iterates over all tiles that are not empty
+    pub fn iter(&self) -> impl Iterator<Item = (UVec2, &RoomTile)> {
+        let width = self.size.x;
+        self.tiles
+            .iter()
+            .enumerate()
+            .filter_map(move |(index, tile)| {
+                let index = index as u32;
+                tile.as_ref()
+                    .map(|tile| (UVec2::new(index % width, index / width), tile))
+            })
+    }
+}
+
+/// The on-disk representation of a room.
+///
+/// Every character in `rows` is looked up in the `legend`, the first row is the top of the room.
+/// The `rows` make up the floor, further `layers` are drawn above it. A space leaves a tile empty.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RoomDescription {
+    pub legend: BTreeMap<char, RoomTile>,
+    pub rows: Vec<String>,
+    #[serde(default)]
+    pub layers: Vec<LayerDescription>,
+}
+
+/// A layer above the floor
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LayerDescription {
+    pub kind: LayerKind,
+    #[serde(default)]
+    pub collidable: Option<bool>,
+    pub rows: Vec<String>,
+}
+
+/// The symbol for an empty tile, it cannot be used in the legend
+const EMPTY_SYMBOL: char = ' ';
+/// The symbols used when writing out a legend
+const LEGEND_SYMBOLS: &str =
+    ".#o@DL0123456789abcdefghijklmnpqrstuvwxyzABCEFGHIJKMNOPQRSTUVWXYZ+-*=%&$!?~^";
+
+impl RoomDescription {
+    fn parse_rows(
+        &self,
+        rows: &[String],
+        size: UVec2,
+        kind: LayerKind,
+    ) -> anyhow::Result<RoomLayer> {
+        if rows.len() as u32 != size.y {
+            bail!(
+                "{:?} layer has {} rows, expected {}",
+                kind,
+                rows.len(),
+                size.y
+            );
+        }
+
+        let mut layer = RoomLayer::new(size, kind);
+
+        for (row_index, row) in rows.iter().enumerate() {
+            if row.chars().count() as u32 != size.x {
+                bail!(
+                    "Row {} of the {:?} layer is {} tiles wide, expected {}",
+                    row_index,
+                    kind,
+                    row.chars().count(),
+                    size.x
+                );
+            }
+
+            let y = size.y - 1 - row_index as u32;
+            for (x, symbol) in row.chars().enumerate() {
+                if symbol == EMPTY_SYMBOL {
+                    continue;
+                }
+
+                let tile = match self.legend.get(&symbol) {
+                    Some(tile) => tile,
+                    None => bail!(
+                        "Symbol {:?} in row {} is not in the legend",
+                        symbol,
+                        row_index
+                    ),
+                };
+
+                layer.set(UVec2::new(x as u32, y), Some(tile.clone()));
+            }
+        }
+
+        Ok(layer)
+    }
+
+    /// Describes an existing room, the chunk and texture settings of the room are not kept
+    pub fn from_room(room: &Room) -> anyhow::Result<Self> {
+        let (floor, layers) = match room.layers().split_first() {
+            Some((floor, layers)) if floor.kind == LayerKind::Floor => (floor, layers),
+            _ => bail!("Rooms without a floor layer cannot be described"),
+        };
+
+        let mut symbols = LEGEND_SYMBOLS.chars();
+        let mut legend: Vec<(char, RoomTile)> = vec![];
+
+        let mut describe_rows = |layer: &RoomLayer| -> anyhow::Result<Vec<String>> {
+            let mut rows = vec![];
+            for y in (0..room.size.y).rev() {
+                let mut row = String::new();
+                for x in 0..room.size.x {
+                    let tile = match layer.get(UVec2::new(x, y)) {
+                        Some(tile) => tile,
+                        None => {
+                            row.push(EMPTY_SYMBOL);
+                            continue;
+                        }
+                    };
+
+                    let symbol = match legend.iter().find(|(_, existing)| existing == tile) {
+                        Some((symbol, _)) => *symbol,
+                        None => {
+                            let symbol = match symbols.next() {
+                                Some(symbol) => symbol,
+                                None => bail!("Room uses too many different tiles to describe"),
+                            };
+                            legend.push((symbol, tile.clone()));
+                            symbol
+                        }
+                    };
+                    row.push(symbol);
+                }
+                rows.push(row);
+            }
+            Ok(rows)
+        };
+
+        let rows = describe_rows(floor)?;
+        let layers = layers
+            .iter()
+            .map(|layer| {
+                Ok(LayerDescription {
+                    kind: layer.kind,
+                    collidable: Some(layer.collidable),
+                    rows: describe_rows(layer)?,
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(RoomDescription {
+            legend: legend.into_iter().collect(),
+            rows,
+            layers,
+        })
+    }
+
+    pub fn into_room(self) -> anyhow::Result<Room> {
+        let height = self.rows.len() as u32;
+        let width = self.rows.first().map_or(0, |row| row.chars().count()) as u32;
+        let size = UVec2::new(width, height);
+
+        let mut room = Room::new(size, RoomSettings::default());
+        room.add_layer(self.parse_rows(&self.rows, size, LayerKind::Floor)?);
+
+        for description in &self.layers {
+            let mut layer = self.parse_rows(&description.rows, size, description.kind)?;
+            if let Some(collidable) = description.collidable {
+                layer.collidable = collidable;
+            }
+            room.add_layer(layer);
+        }
+
+        Ok(room)
+    }
+}
+
+#[derive(Default)]
+pub struct RoomLoader;
+
+impl AssetLoader for RoomLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let description: RoomDescription = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(description.into_room()?));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["room"]
+    }
+}
@@ -0,0 +1,62 @@
+use bevy::{math::Vec3Swizzles, prelude::*, utils::HashMap};
+
+use super::Surface;
+use crate::{
+    coords::MapGeometry,
+    movement::{Movement, MovementChannel, MovementModifier, Movements},
+};
+
+const SURFACE_PUSH: MovementChannel = MovementChannel("surface_push");
+/// How much of the push gets added every second, a push takes a quarter second to get going
+const PUSH_ACCELERATION: f32 = 4.;
+/// A push is only kept up while standing on it, afterwards it fades out quickly
+const PUSH_DAMPENING: f32 = 0.001;
+
+/// The surface tiles of the current room, by position
+#[derive(Default)]
+pub(super) struct SurfaceTiles(HashMap<(u32, u32), Surface>);
+
+pub(super) fn apply_surfaces(
+    mut surface_tiles: Local<SurfaceTiles>,
+    map_geometry: Option<Res<MapGeometry>>,
+    surface_query: Query<(&UVec2, &Surface)>,
+    mut mover_query: Query<(&Transform, &mut Movements)>,
+) {
+    let map_geometry = if let Some(map_geometry) = map_geometry {
+        map_geometry
+    } else {
+        return;
+    };
+
+    // The tiles only change when a room gets built
+    if map_geometry.is_changed() {
+        surface_tiles.0.clear();
+        surface_tiles.0.extend(
+            surface_query
+                .iter()
+                .map(|(position, surface)| ((position.x, position.y), *surface)),
+        );
+    }
+
+    for (transform, mut movements) in mover_query.iter_mut() {
+        let surface = map_geometry
+            .world_to_tile(transform.translation.xy())
+            .and_then(|tile| surface_tiles.0.get(&(tile.x, tile.y)))
+            .copied()
+            .unwrap_or_default();
+
+        movements.set_ground(surface.friction, surface.speed);
+
+        let push = Vec3::new(surface.push[0], surface.push[1], 0.);
+        if push != Vec3::ZERO {
+            movements.add(Movement::new(
+                SURFACE_PUSH,
+                MovementModifier::Momentum {
+                    acceleration: push * PUSH_ACCELERATION,
+                    dampening: PUSH_DAMPENING,
+                    maximal_velocity: Some(push.length()),
+                },
+            ));
+        }
+    }
+}
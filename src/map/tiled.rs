@@ -0,0 +1,387 @@
+use anyhow::bail;
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    utils::BoxedFuture,
+};
+use serde::Deserialize;
+
+use super::room::{LayerKind, Room, RoomLayer, RoomSettings, SpawnPoint, Surface, TileAnimation};
+
+/// Tiled stores flipping in the upper bits of a gid, we do not support flipped tiles so they get masked off
+const GID_FLAGS: u32 = 0xE000_0000;
+
+#[derive(Debug, Deserialize)]
+struct TiledMap {
+    width: u32,
+    height: u32,
+    tilewidth: u32,
+    tileheight: u32,
+    layers: Vec<TiledLayer>,
+    tilesets: Vec<TiledTileset>,
+    #[serde(default)]
+    properties: Vec<TiledProperty>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum TiledLayer {
+    Tilelayer {
+        name: String,
+        data: Vec<u32>,
+        #[serde(default)]
+        properties: Vec<TiledProperty>,
+    },
+    Objectgroup {
+        name: String,
+        objects: Vec<TiledObject>,
+    },
+    #[serde(other)]
+    Unsupported,
+}
+
+#[derive(Debug, Deserialize)]
+struct TiledObject {
+    #[serde(rename = "type", alias = "class", default)]
+    kind: String,
+    x: f32,
+    y: f32,
+    #[serde(default)]
+    width: f32,
+    #[serde(default)]
+    height: f32,
+    #[serde(default)]
+    gid: Option<u32>,
+    #[serde(default)]
+    properties: Vec<TiledProperty>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TiledTileset {
+    firstgid: u32,
+    #[serde(default)]
+    imagewidth: Option<u32>,
+    #[serde(default)]
+    imageheight: Option<u32>,
+    #[serde(default)]
+    tiles: Vec<TiledTile>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TiledTile {
+    id: u32,
+    #[serde(default)]
+    properties: Vec<TiledProperty>,
+    #[serde(default)]
+    animation: Vec<TiledFrame>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TiledFrame {
+    tileid: u32,
+    /// In milliseconds
+    duration: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct TiledProperty {
+    name: String,
+    value: serde_json::Value,
+}
+
+fn find_property<'a>(properties: &'a [TiledProperty], name: &str) -> Option<&'a serde_json::Value> {
+    properties
+        .iter()
+        .find(|property| property.name == name)
+        .map(|property| &property.value)
+}
+
+impl TiledMap {
+    fn settings(&self) -> RoomSettings {
+        let default = RoomSettings::default();
+
+        let chunk_dimension = |name: &str, default: u32| {
+            find_property(&self.properties, name)
+                .and_then(|value| value.as_u64())
+                .map_or(default, |value| value as u32)
+        };
+
+        let tileset = self.tilesets.first();
+
+        RoomSettings {
+            chunk_size: UVec2::new(
+                chunk_dimension("chunk_width", default.chunk_size.x),
+                chunk_dimension("chunk_height", default.chunk_size.y),
+            ),
+            tile_size: Vec2::new(self.tilewidth as f32, self.tileheight as f32),
+            texture_size: Vec2::new(
+                tileset
+                    .and_then(|tileset| tileset.imagewidth)
+                    .map_or(default.texture_size.x, |width| width as f32),
+                tileset
+                    .and_then(|tileset| tileset.imageheight)
+                    .map_or(default.texture_size.y, |height| height as f32),
+            ),
+        }
+    }
+
+    /// The tileset a gid belongs to, `None` if the gid is empty
+    fn tileset(&self, gid: u32) -> Option<&TiledTileset> {
+        let gid = gid & !GID_FLAGS;
+        if gid == 0 {
+            return None;
+        }
+
+        self.tilesets
+            .iter()
+            .filter(|tileset| tileset.firstgid <= gid)
+            .max_by_key(|tileset| tileset.firstgid)
+    }
+
+    /// Converts a gid into an index into the atlas, `None` if the gid is empty
+    fn texture_index(&self, gid: u32) -> Option<u16> {
+        let tileset = self.tileset(gid)?;
+        Some(((gid & !GID_FLAGS) - tileset.firstgid) as u16)
+    }
+
+    /// The extra data the tileset stores about a tile, if there is any
+    fn tile_data(&self, gid: u32) -> Option<&TiledTile> {
+        let texture_index = self.texture_index(gid)? as u32;
+        self.tileset(gid)?
+            .tiles
+            .iter()
+            .find(|tile| tile.id == texture_index)
+    }
+
+    /// Tiles can be marked as walls in the tileset with a boolean `wall` property
+    fn is_wall(&self, gid: u32) -> bool {
+        self.tile_data(gid)
+            .and_then(|tile| find_property(&tile.properties, "wall"))
+            .and_then(|value| value.as_bool())
+            == Some(true)
+    }
+
+    /// Surfaces come from the float tile properties `friction`, `speed`, `push_x` and `push_y`, a
+    /// tile without any of them is a plain floor
+    fn surface(&self, gid: u32) -> Option<Surface> {
+        let properties = &self.tile_data(gid)?.properties;
+        let property = |name| find_property(properties, name).and_then(|value| value.as_f64());
+
+        let (friction, speed, push_x, push_y) = (
+            property("friction"),
+            property("speed"),
+            property("push_x"),
+            property("push_y"),
+        );
+
+        if friction.is_none() && speed.is_none() && push_x.is_none() && push_y.is_none() {
+            return None;
+        }
+
+        Some(Surface {
+            friction: friction.unwrap_or(1.) as f32,
+            speed: speed.unwrap_or(1.) as f32,
+            // Tiled counts y downwards
+            push: [push_x.unwrap_or(0.) as f32, -push_y.unwrap_or(0.) as f32],
+        })
+    }
+
+    /// Only animations running through consecutive tiles can be shown, the frame durations get
+    /// averaged
+    fn animation(&self, gid: u32) -> Option<TileAnimation> {
+        let frames = &self.tile_data(gid)?.animation;
+        let first = frames.first()?;
+
+        let consecutive = frames
+            .iter()
+            .enumerate()
+            .all(|(index, frame)| frame.tileid == first.tileid + index as u32);
+
+        if !consecutive {
+            warn!(
+                "Animation of tile {} does not use consecutive tiles, it will not be animated",
+                gid
+            );
+            return None;
+        }
+
+        let duration: u32 = frames.iter().map(|frame| frame.duration).sum();
+
+        Some(TileAnimation {
+            start: first.tileid,
+            end: first.tileid + frames.len() as u32,
+            speed: 1000. / duration.max(1) as f32,
+        })
+    }
+
+    /// All tile positions covered by the object, Tiled has its origin in the top left corner
+    fn covered_tiles(&self, object: &TiledObject) -> Vec<UVec2> {
+        let tile_width = self.tilewidth as f32;
+        let tile_height = self.tileheight as f32;
+
+        // Tile objects are anchored at their bottom left corner instead of the top left one
+        let top = if object.gid.is_some() {
+            object.y - object.height
+        } else {
+            object.y
+        };
+
+        let (width, height) = if object.width == 0. && object.height == 0. {
+            // Point objects cover the tile they are placed on
+            (1., 1.)
+        } else {
+            (object.width, object.height)
+        };
+
+        let start_column = (object.x / tile_width).floor().max(0.) as u32;
+        let end_column = ((object.x + width) / tile_width)
+            .ceil()
+            .min(self.width as f32) as u32;
+        let start_row = (top / tile_height).floor().max(0.) as u32;
+        let end_row = ((top + height) / tile_height)
+            .ceil()
+            .min(self.height as f32) as u32;
+
+        let mut tiles = vec![];
+        for row in start_row..end_row {
+            for column in start_column..end_column {
+                tiles.push(UVec2::new(column, self.height - 1 - row));
+            }
+        }
+        tiles
+    }
+
+    fn into_room(self) -> anyhow::Result<Room> {
+        // Every layer is drawn from the one atlas texture, so tiles of a second tileset would
+        // silently show the sprites at the same index of the first one
+        if self.tilesets.len() > 1 {
+            bail!(
+                "Map uses {} tilesets, only a single one is supported",
+                self.tilesets.len()
+            );
+        }
+
+        let size = UVec2::new(self.width, self.height);
+        let mut room = Room::new(size, self.settings());
+        let mut previous_layer = None;
+
+        for layer in &self.layers {
+            match layer {
+                TiledLayer::Tilelayer {
+                    name,
+                    data,
+                    properties,
+                } => {
+                    if data.len() != (self.width * self.height) as usize {
+                        bail!(
+                            "Layer {:?} has {} tiles, expected {}",
+                            name,
+                            data.len(),
+                            self.width * self.height
+                        );
+                    }
+
+                    // The first layer is the floor unless a `kind` property says otherwise
+                    let kind =
+                        match find_property(properties, "kind").and_then(|kind| kind.as_str()) {
+                            Some("floor") => LayerKind::Floor,
+                            Some("decoration") => LayerKind::Decoration,
+                            Some("overhead") => LayerKind::Overhead,
+                            Some(kind) => bail!("Layer {:?} has unknown kind {:?}", name, kind),
+                            None if room.layers().is_empty() => LayerKind::Floor,
+                            None => LayerKind::Decoration,
+                        };
+
+                    let mut room_layer = RoomLayer::new(size, kind);
+                    if let Some(collidable) =
+                        find_property(properties, "collidable").and_then(|value| value.as_bool())
+                    {
+                        room_layer.collidable = collidable;
+                    }
+
+                    for (index, gid) in data.iter().copied().enumerate() {
+                        let index = index as u32;
+                        let position =
+                            UVec2::new(index % self.width, self.height - 1 - index / self.width);
+
+                        if let Some(texture_index) = self.texture_index(gid) {
+                            let tile = room_layer.get_or_insert(position).unwrap();
+                            tile.texture_index = texture_index;
+                            tile.wall = self.is_wall(gid);
+                            tile.animation = self.animation(gid);
+                            tile.surface = self.surface(gid);
+                        }
+                    }
+
+                    previous_layer = Some(room.add_layer(room_layer));
+                }
+                TiledLayer::Objectgroup { name, objects } => {
+                    // Objects belong to the tile layer they are drawn over
+                    let room_layer = match previous_layer {
+                        Some(index) => &mut room.layers_mut()[index],
+                        None => bail!("Object layer {:?} has no tile layer below it", name),
+                    };
+
+                    for object in objects {
+                        for position in self.covered_tiles(object) {
+                            let tile = room_layer.get_or_insert(position).unwrap();
+
+                            if let Some(texture_index) =
+                                object.gid.and_then(|gid| self.texture_index(gid))
+                            {
+                                tile.texture_index = texture_index;
+                            }
+
+                            match object.kind.as_str() {
+                                "wall" => tile.wall = true,
+                                "spawn" | "player" => tile.spawn = Some(SpawnPoint::Player),
+                                "door" => {
+                                    let door = find_property(&object.properties, "door")
+                                        .and_then(|value| value.as_u64())
+                                        .unwrap_or(0);
+                                    tile.spawn = Some(SpawnPoint::Door(door as u32));
+                                }
+                                "crate" => {
+                                    tile.wall = true;
+                                    tile.spawn = Some(SpawnPoint::Crate);
+                                }
+                                "" => {}
+                                kind => warn!("Unknown object type {:?} in layer {:?}", kind, name),
+                            }
+                        }
+                    }
+                }
+                TiledLayer::Unsupported => {}
+            }
+        }
+
+        if room.layers().is_empty() {
+            bail!("Map does not contain any tile layers");
+        }
+
+        Ok(room)
+    }
+}
+
+/// Loads maps exported from Tiled in the JSON format with uncompressed layer data and a single
+/// tileset
+#[derive(Default)]
+pub struct TiledLoader;
+
+impl AssetLoader for TiledLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let map: TiledMap = serde_json::from_slice(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(map.into_room()?));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tmj", "json"]
+    }
+}
@@ -0,0 +1,134 @@
+//! Steps the movements of a crowd far larger than any room will hold, to make sure it does not
+//! allocate once every entity has room for its effects.
+//!
+//! Run with `cargo test --release bench -- --nocapture` to see how long it takes.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    f32::consts::TAU,
+    time::Instant,
+};
+
+use bevy::prelude::*;
+
+use super::{Easing, Movement, MovementChannel, MovementModifier, Movements, MOVEMENT_TIMESTEP};
+
+const CROWD_SIZE: usize = 5000;
+/// Enough steps for every entity to have had each of its movements at least once
+const WARM_UP_STEPS: usize = 100;
+const MEASURED_STEPS: usize = 600;
+
+const WANDER: MovementChannel = MovementChannel("wander");
+const SHOVE: MovementChannel = MovementChannel("shove");
+const KNOCKBACK: MovementChannel = MovementChannel("knockback");
+
+/// Counts allocations per thread, so tests running alongside this one do not show up
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = Cell::new(0);
+}
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(Cell::get)
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// A direction with the given length that looks random but is the same on every run
+fn direction(seed: usize, length: f32) -> Vec3 {
+    const GOLDEN_RATIO_CONJUGATE: f32 = 0.618_034;
+    let angle = (seed as f32 * GOLDEN_RATIO_CONJUGATE).fract() * TAU;
+    Vec3::new(angle.cos(), angle.sin(), 0.) * length
+}
+
+/// Every entity wanders around and now and then gets shoved or knocked back, like enemies in a
+/// fight would
+fn move_crowd(crowd: &mut [Movements], step: usize) -> Vec3 {
+    let mut total = Vec3::ZERO;
+
+    for (index, movements) in crowd.iter_mut().enumerate() {
+        let seed = index * 31 + step;
+
+        movements.add(Movement::new(
+            WANDER,
+            MovementModifier::Momentum {
+                acceleration: direction(seed, 128.),
+                dampening: 0.01,
+                maximal_velocity: Some(64.),
+            },
+        ));
+
+        match seed % 100 {
+            0 => movements.add(Movement::new(
+                SHOVE,
+                MovementModifier::Impulse {
+                    impulse: direction(seed + 1, 96.),
+                },
+            )),
+            1 => movements.add(Movement::new(
+                KNOCKBACK,
+                MovementModifier::Timed {
+                    velocity: direction(seed + 2, 192.),
+                    duration: 0.2,
+                    easing: Easing::EaseOut,
+                },
+            )),
+            2 => movements.cancel(KNOCKBACK),
+            _ => (),
+        }
+
+        total += movements.step(MOVEMENT_TIMESTEP as f32);
+    }
+
+    total
+}
+
+#[test]
+fn stepping_a_crowd_does_not_allocate() {
+    let mut crowd: Vec<_> = (0..CROWD_SIZE).map(|_| Movements::new()).collect();
+
+    for step in 0..WARM_UP_STEPS {
+        move_crowd(&mut crowd, step);
+    }
+
+    let allocations_before = allocations();
+    let start = Instant::now();
+
+    let mut total = Vec3::ZERO;
+    for step in WARM_UP_STEPS..WARM_UP_STEPS + MEASURED_STEPS {
+        total += move_crowd(&mut crowd, step);
+    }
+
+    let elapsed = start.elapsed();
+    let allocated = allocations() - allocations_before;
+
+    println!(
+        "Stepped {} entities {} times in {:?}, {:?} per step",
+        CROWD_SIZE,
+        MEASURED_STEPS,
+        elapsed,
+        elapsed / MEASURED_STEPS as u32
+    );
+
+    assert!(total.is_finite());
+    assert_eq!(allocated, 0, "Stepping the crowd allocated");
+}
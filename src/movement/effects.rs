@@ -0,0 +1,508 @@
+use bevy::{math::Vec3A, prelude::*};
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum MovementModifier {
+    /// An impulse is directly added to the velocity, bypassing the acceleration
+    Impulse { impulse: Vec3 },
+    /// Momentum is acceleration, up to the specified cap in velocity
+    Momentum {
+        acceleration: Vec3,
+        dampening: f32,
+        maximal_velocity: Option<f32>,
+    },
+    /// A velocity that lasts exactly `duration` seconds, fading out as given by `easing`
+    Timed {
+        velocity: Vec3,
+        duration: f32,
+        easing: Easing,
+    },
+}
+
+/// How the strength of a timed movement changes over its duration
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub enum Easing {
+    /// Full strength until it runs out
+    Constant,
+    /// Fades out evenly
+    Linear,
+    /// Stays strong for longer and drops off towards the end
+    EaseIn,
+    /// Drops off quickly at first, this is what dashes and knockback feel best with
+    EaseOut,
+}
+
+impl Easing {
+    /// The strength at `progress`, which goes from 0 at the start to 1 at the end
+    pub fn strength(self, progress: f32) -> f32 {
+        let progress = progress.clamp(0., 1.);
+
+        match self {
+            Easing::Constant => 1.,
+            Easing::Linear => 1. - progress,
+            Easing::EaseIn => 1. - progress * progress,
+            Easing::EaseOut => (1. - progress) * (1. - progress),
+        }
+    }
+}
+
+/// The part of an effect that only timed movements have
+#[derive(Debug)]
+struct EffectTimer {
+    velocity: Vec3,
+    elapsed: f32,
+    duration: f32,
+    easing: Easing,
+}
+
+#[derive(Default, Debug)]
+struct MovementEffect {
+    velocity: Vec3,
+    acceleration: Vec3,
+    dampening: f32,
+    maximal_velocity: f32,
+    maximal_acceleration: f32,
+    updated: bool,
+    timer: Option<EffectTimer>,
+    blend: Blend,
+    priority: i32,
+}
+
+/// How an effect gets combined with the effects of lower priority
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Default)]
+pub enum Blend {
+    /// Adds its velocity to theirs
+    #[default]
+    Additive,
+    /// Replaces their velocity, e.g. knockback taking over from walking
+    Override,
+    /// Uses whichever velocity is the faster one
+    MaxMagnitude,
+}
+
+/// Identifies a movement, adding another one on the same channel replaces or refreshes it.
+///
+/// Channels are plain constants, e.g. `const DASH: MovementChannel = MovementChannel("dash");`,
+/// so adding movements every frame does not allocate.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub struct MovementChannel(pub &'static str);
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Movement {
+    pub modifier: MovementModifier,
+    pub channel: MovementChannel,
+    pub blend: Blend,
+    /// Effects are combined from the lowest priority up, those with the same priority in the order
+    /// they were first added
+    pub priority: i32,
+}
+
+impl Movement {
+    pub fn new(channel: MovementChannel, modifier: MovementModifier) -> Self {
+        Movement {
+            channel,
+            modifier,
+            blend: Blend::Additive,
+            priority: 0,
+        }
+    }
+
+    pub fn with_blend(mut self, blend: Blend) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+}
+
+/// Velocities below this are treated as standing still
+pub const SMALLEST_MAGNITUDE: f32 = 0.0005;
+
+/// The movements of an entity.
+///
+/// Entities only ever have a handful of effects at once, so they are kept in plain vectors which
+/// keep their capacity from step to step instead of being hashed.
+pub struct Movements {
+    movements: Vec<Movement>,
+    current_effects: Vec<(MovementChannel, MovementEffect)>,
+    /// Scales how quickly momentum dies down, set from what the entity stands on
+    friction: f32,
+    /// Scales the maximal velocity of momentum
+    speed: f32,
+}
+
+impl Default for Movements {
+    fn default() -> Self {
+        Movements {
+            movements: vec![],
+            current_effects: vec![],
+            friction: 1.,
+            speed: 1.,
+        }
+    }
+}
+
+impl Movements {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Changes how the ground affects momentum until it gets set again, 1 leaves it unchanged
+    pub fn set_ground(&mut self, friction: f32, speed: f32) {
+        self.friction = friction;
+        self.speed = speed;
+    }
+
+    pub fn add(&mut self, movement: Movement) {
+        self.movements.push(movement);
+    }
+
+    /// Advances every effect by `delta_time` seconds and returns the velocity they add up to.
+    ///
+    /// Movements added since the last step take effect first, this is all there is to simulating
+    /// movement, so it can be driven without any systems.
+    pub fn step(&mut self, delta_time: f32) -> Vec3 {
+        self.process_new_movements();
+        self.update_movements(delta_time);
+        self.get_total_direction()
+    }
+
+    /// Whether an effect is running on the channel
+    pub fn is_active(&self, channel: MovementChannel) -> bool {
+        self.current_effects
+            .iter()
+            .any(|(effect_channel, _)| *effect_channel == channel)
+    }
+
+    fn process_new_movements(&mut self) {
+        let Movements {
+            movements,
+            current_effects,
+            ..
+        } = self;
+
+        for movement in movements.drain(..) {
+            match movement.modifier {
+                MovementModifier::Impulse { impulse: velocity } => {
+                    *effect_entry(current_effects, movement.channel) = MovementEffect {
+                        acceleration: Vec3::ZERO,
+                        dampening: 0.05,
+                        updated: true,
+                        maximal_velocity: f32::MAX,
+                        maximal_acceleration: f32::MAX,
+                        velocity,
+                        ..Default::default()
+                    };
+                }
+                MovementModifier::Momentum {
+                    acceleration,
+                    dampening,
+                    maximal_velocity,
+                } => {
+                    let entry = effect_entry(current_effects, movement.channel);
+                    entry.acceleration = acceleration;
+                    entry.dampening = dampening;
+                    entry.maximal_velocity = maximal_velocity.unwrap_or(f32::MAX);
+                    entry.updated = true;
+                }
+                MovementModifier::Timed {
+                    velocity,
+                    duration,
+                    easing,
+                } => {
+                    // Adding a timed movement again restarts it
+                    *effect_entry(current_effects, movement.channel) = MovementEffect {
+                        updated: true,
+                        maximal_velocity: f32::MAX,
+                        maximal_acceleration: f32::MAX,
+                        timer: Some(EffectTimer {
+                            velocity,
+                            elapsed: 0.,
+                            duration,
+                            easing,
+                        }),
+                        ..Default::default()
+                    };
+                }
+            }
+
+            let effect = effect_entry(current_effects, movement.channel);
+            effect.blend = movement.blend;
+            effect.priority = movement.priority;
+        }
+
+        // This is a stable sort, so effects of the same priority keep their order
+        current_effects.sort_by_key(|(_, effect)| effect.priority);
+    }
+
+    /// Stops the effect on the channel right away, this includes movements added since the last
+    /// update
+    pub fn cancel(&mut self, channel: MovementChannel) {
+        self.movements
+            .retain(|movement| movement.channel != channel);
+        self.current_effects
+            .retain(|(effect_channel, _)| *effect_channel != channel);
+    }
+
+    fn update_movements(&mut self, delta_time: f32) {
+        let (friction, speed) = (self.friction, self.speed);
+
+        for (_, effect) in self.current_effects.iter_mut() {
+            // Timed effects follow their curve and ignore dampening altogether
+            if let Some(timer) = &mut effect.timer {
+                let progress = if timer.duration > 0. {
+                    timer.elapsed / timer.duration
+                } else {
+                    1.
+                };
+                effect.velocity = timer.velocity * timer.easing.strength(progress);
+                timer.elapsed += delta_time;
+                effect.updated = false;
+                continue;
+            }
+
+            if !effect.updated {
+                effect.velocity *= effect.dampening.powf(delta_time * friction);
+            }
+            effect.velocity += effect.acceleration * delta_time;
+
+            if !effect.updated {
+                effect.acceleration *= effect.dampening.powf(delta_time * friction);
+            }
+
+            // effect.velocity = effect.velocity.length() * effect.acceleration.normalize().lerp(effect.velocity.normalize(), 0.75).normalize();
+
+            effect.velocity = effect
+                .velocity
+                .clamp_length(0., effect.maximal_velocity * speed);
+
+            if effect.velocity.x.abs() < SMALLEST_MAGNITUDE {
+                effect.velocity.x = 0.;
+            }
+            if effect.velocity.y.abs() < SMALLEST_MAGNITUDE {
+                effect.velocity.y = 0.;
+            }
+            if effect.velocity.z.abs() < SMALLEST_MAGNITUDE {
+                effect.velocity.z = 0.;
+            }
+
+            effect.updated = false;
+        }
+
+        self.current_effects
+            .retain(|(_, effect)| match &effect.timer {
+                Some(timer) => timer.elapsed < timer.duration,
+                None => {
+                    effect.velocity.length() > SMALLEST_MAGNITUDE
+                        || effect.acceleration.length() > SMALLEST_MAGNITUDE
+                }
+            });
+    }
+
+    fn get_total_direction(&self) -> Vec3 {
+        self.current_effects
+            .iter()
+            .fold(Vec3A::ZERO, |total, (_, effect)| {
+                let velocity = Vec3A::from(effect.velocity);
+
+                match effect.blend {
+                    Blend::Additive => total + velocity,
+                    Blend::Override => velocity,
+                    Blend::MaxMagnitude if velocity.length() > total.length() => velocity,
+                    Blend::MaxMagnitude => total,
+                }
+            })
+            .into()
+    }
+}
+
+/// The effect on the channel, a new one is added if there is none yet
+fn effect_entry(
+    effects: &mut Vec<(MovementChannel, MovementEffect)>,
+    channel: MovementChannel,
+) -> &mut MovementEffect {
+    let index = match effects
+        .iter()
+        .position(|(effect_channel, _)| *effect_channel == channel)
+    {
+        Some(index) => index,
+        None => {
+            effects.push((channel, MovementEffect::default()));
+            effects.len() - 1
+        }
+    };
+
+    &mut effects[index].1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEP: f32 = 1. / 60.;
+    const FIRST: MovementChannel = MovementChannel("first");
+    const SECOND: MovementChannel = MovementChannel("second");
+
+    fn assert_close(actual: Vec3, expected: Vec3) {
+        assert!(
+            (actual - expected).length() < 1e-3,
+            "{:?} is not close to {:?}",
+            actual,
+            expected
+        );
+    }
+
+    fn impulse(channel: MovementChannel, impulse: Vec3) -> Movement {
+        Movement::new(channel, MovementModifier::Impulse { impulse })
+    }
+
+    #[test]
+    fn impulses_decay_over_time() {
+        let mut movements = Movements::new();
+        movements.add(impulse(FIRST, Vec3::X));
+
+        // The impulse is added in full before it starts to decay
+        assert_close(movements.step(STEP), Vec3::X);
+
+        let mut previous = Vec3::X;
+        for _ in 0..60 {
+            let velocity = movements.step(STEP);
+            assert!(velocity.length() < previous.length());
+            assert_eq!(velocity.y, 0.);
+            previous = velocity;
+        }
+
+        // An impulse loses all but 5% of its speed every second
+        assert_close(previous, Vec3::X * 0.05);
+    }
+
+    #[test]
+    fn decayed_effects_are_pruned() {
+        let mut movements = Movements::new();
+        movements.add(impulse(FIRST, Vec3::X));
+
+        for _ in 0..4 * 60 {
+            movements.step(STEP);
+        }
+
+        assert!(!movements.is_active(FIRST));
+        assert_eq!(movements.step(STEP), Vec3::ZERO);
+    }
+
+    #[test]
+    fn momentum_is_capped_at_its_maximal_velocity() {
+        let mut movements = Movements::new();
+        let push = Movement::new(
+            FIRST,
+            MovementModifier::Momentum {
+                acceleration: Vec3::new(600., 0., 0.),
+                dampening: 0.5,
+                maximal_velocity: Some(10.),
+            },
+        );
+
+        let mut velocity = Vec3::ZERO;
+        for _ in 0..60 {
+            movements.add(push);
+            velocity = movements.step(STEP);
+            assert!(velocity.length() <= 10. + 1e-3);
+        }
+        assert_close(velocity, Vec3::new(10., 0., 0.));
+
+        // Slow ground lowers the cap
+        movements.set_ground(1., 0.5);
+        movements.add(push);
+        assert_close(movements.step(STEP), Vec3::new(5., 0., 0.));
+    }
+
+    #[test]
+    fn momentum_dies_down_once_it_is_no_longer_added() {
+        let mut movements = Movements::new();
+        movements.add(Movement::new(
+            FIRST,
+            MovementModifier::Momentum {
+                acceleration: Vec3::new(60., 0., 0.),
+                dampening: 0.01,
+                maximal_velocity: None,
+            },
+        ));
+
+        let first = movements.step(STEP);
+        assert_close(first, Vec3::new(1., 0., 0.));
+
+        let second = movements.step(STEP);
+        assert!(second.x > 0.);
+
+        for _ in 0..10 * 60 {
+            movements.step(STEP);
+        }
+        assert!(!movements.is_active(FIRST));
+    }
+
+    #[test]
+    fn effects_on_different_channels_stack() {
+        let mut movements = Movements::new();
+        movements.add(impulse(FIRST, Vec3::X));
+        movements.add(impulse(SECOND, Vec3::Y * 2.));
+
+        assert_close(movements.step(STEP), Vec3::new(1., 2., 0.));
+        assert!(movements.is_active(FIRST));
+        assert!(movements.is_active(SECOND));
+    }
+
+    #[test]
+    fn effects_on_the_same_channel_get_replaced() {
+        let mut movements = Movements::new();
+        movements.add(impulse(FIRST, Vec3::X));
+        movements.add(impulse(SECOND, Vec3::Y * 2.));
+        movements.step(STEP);
+
+        movements.add(impulse(FIRST, Vec3::X * 3.));
+        let velocity = movements.step(STEP);
+
+        // The new impulse replaces the old one, the other channel keeps decaying
+        assert_close(velocity * Vec3::X, Vec3::X * 3.);
+        assert!(velocity.y > 0. && velocity.y < 2.);
+    }
+
+    #[test]
+    fn override_takes_over_from_lower_priorities() {
+        let mut movements = Movements::new();
+        movements.add(impulse(FIRST, Vec3::X));
+        movements.add(
+            impulse(SECOND, Vec3::Y * 4.)
+                .with_blend(Blend::Override)
+                .with_priority(10),
+        );
+
+        assert_close(movements.step(STEP), Vec3::Y * 4.);
+
+        movements.cancel(SECOND);
+        assert!(!movements.is_active(SECOND));
+        assert!(movements.step(STEP).x > 0.);
+    }
+
+    #[test]
+    fn timed_effects_follow_their_easing_and_expire() {
+        let mut movements = Movements::new();
+        movements.add(Movement::new(
+            FIRST,
+            MovementModifier::Timed {
+                velocity: Vec3::X * 8.,
+                duration: 1.,
+                easing: Easing::Linear,
+            },
+        ));
+
+        assert_close(movements.step(0.25), Vec3::X * 8.);
+        assert_close(movements.step(0.25), Vec3::X * 6.);
+        assert_close(movements.step(0.25), Vec3::X * 4.);
+
+        // The step that uses up the duration already leaves it out
+        assert_eq!(movements.step(0.25), Vec3::ZERO);
+        assert!(!movements.is_active(FIRST));
+        assert_eq!(movements.step(0.25), Vec3::ZERO);
+    }
+}
@@ -0,0 +1,255 @@
+use bevy::{
+    core::{FixedTimestep, FixedTimesteps},
+    math::Vec3Swizzles,
+    prelude::*,
+    transform::TransformSystem,
+};
+use bevy_rapier2d::{
+    physics::{
+        IntoEntity, QueryPipelineColliderComponentsQuery, QueryPipelineColliderComponentsSet,
+    },
+    prelude::{ColliderHandle, ColliderShape, QueryPipeline, RigidBodyPosition, RigidBodyType},
+    rapier::{
+        math::{Isometry, Vector},
+        parry::query::TOIStatus,
+    },
+};
+
+use crate::{
+    coords::{physics_to_world, world_to_physics},
+    physics::CollisionLayer,
+};
+
+#[cfg(test)]
+mod bench;
+mod effects;
+
+pub use effects::{
+    Blend, Easing, Movement, MovementChannel, MovementModifier, Movements, SMALLEST_MAGNITUDE,
+};
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, StageLabel)]
+pub struct MovementStage;
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemLabel)]
+pub enum MovementCalculation {
+    /// Systems adding movements, e.g. from player input, run here so they see every step
+    Input,
+    Movements,
+    Velocity,
+    Position,
+    Interpolation,
+}
+
+/// Movement is simulated in steps of this many seconds, no matter the frame rate
+pub const MOVEMENT_TIMESTEP: f64 = 1. / 60.;
+const MOVEMENT_TIMESTEP_LABEL: &str = "movement_timestep";
+
+pub struct MovementPlugin;
+
+impl Plugin for MovementPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_stage_after(
+            CoreStage::Update,
+            MovementStage,
+            SystemStage::parallel().with_run_criteria(
+                FixedTimestep::step(MOVEMENT_TIMESTEP).with_label(MOVEMENT_TIMESTEP_LABEL),
+            ),
+        );
+        app.add_system_to_stage(
+            MovementStage,
+            apply_movements
+                .system()
+                .label(MovementCalculation::Movements),
+        );
+        app.add_system_to_stage(
+            MovementStage,
+            incorporate_velocity
+                .system()
+                .label(MovementCalculation::Velocity)
+                .after(MovementCalculation::Movements),
+        );
+        app.add_system_to_stage(
+            MovementStage,
+            incorporate_velocity_with_collisions
+                .system()
+                .label(MovementCalculation::Velocity)
+                .after(MovementCalculation::Movements),
+        );
+        app.add_system_to_stage(
+            MovementStage,
+            set_position_from_transform
+                .system()
+                .label(MovementCalculation::Position)
+                .before(MovementCalculation::Movements),
+        );
+        app.add_system_to_stage(
+            CoreStage::PostUpdate,
+            interpolate_transforms
+                .system()
+                .label(MovementCalculation::Interpolation)
+                .before(TransformSystem::TransformPropagate),
+        );
+    }
+}
+
+#[derive(Bundle, Default)]
+pub struct MovementBundle {
+    pub position: Position,
+    pub velocity: Velocity,
+    pub movements: Movements,
+}
+
+#[derive(Default, Debug)]
+pub struct Position {
+    translation: Vec3,
+    /// Where the entity was before the last step, the transform gets placed in between the two
+    previous: Vec3,
+    /// Whether the position has been taken from the transform yet
+    placed: bool,
+}
+
+impl Position {
+    /// Moves the entity without going through its velocity
+    pub fn teleport(&mut self, translation: Vec3) {
+        self.translation = translation;
+        self.previous = translation;
+        self.placed = true;
+    }
+
+    fn step_to(&mut self, translation: Vec3) {
+        self.previous = self.translation;
+        self.translation = translation;
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct Velocity {
+    velocity: Vec3,
+}
+
+impl Velocity {
+    /// The velocity all movements added up to in the last step, in pixels per second
+    pub fn velocity(&self) -> Vec3 {
+        self.velocity
+    }
+}
+
+/// Moves entities that are not part of the physics simulation, nothing stops them
+pub fn incorporate_velocity(
+    mut velo_query: Query<(&Velocity, &mut Position), Without<RigidBodyPosition>>,
+) {
+    for (velo, mut position) in velo_query.iter_mut() {
+        let mut translation = position.translation;
+        if velo.velocity.length() > SMALLEST_MAGNITUDE {
+            translation += velo.velocity * MOVEMENT_TIMESTEP as f32;
+        }
+        position.step_to(translation);
+    }
+}
+
+/// How far in rapier units bodies keep away from the walls they slide along
+const CONTACT_SKIN: f32 = 0.01;
+/// Every slide can run into another wall, e.g. in corners
+const MAX_SLIDES: usize = 4;
+
+/// Moves kinematic bodies through rapier, sliding them along static colliders instead of passing
+/// through them
+pub fn incorporate_velocity_with_collisions(
+    mut body_query: Query<(
+        Entity,
+        &Velocity,
+        &mut Position,
+        &mut RigidBodyPosition,
+        &ColliderShape,
+    )>,
+    body_type_query: Query<&RigidBodyType>,
+    query_pipeline: Res<QueryPipeline>,
+    collider_query: QueryPipelineColliderComponentsQuery,
+) {
+    let colliders = QueryPipelineColliderComponentsSet(&collider_query);
+
+    for (entity, velo, mut position, mut body_position, shape) in body_query.iter_mut() {
+        // Only the level blocks movement, everything that moves itself gets resolved through contacts
+        let filter = |handle: ColliderHandle| {
+            let other = handle.entity();
+            other != entity && matches!(body_type_query.get(other), Ok(RigidBodyType::Static))
+        };
+
+        let mut current = world_to_physics(position.translation.xy());
+        let mut remaining = world_to_physics(velo.velocity.xy() * MOVEMENT_TIMESTEP as f32);
+
+        for _ in 0..MAX_SLIDES {
+            let distance = remaining.length();
+            if distance < SMALLEST_MAGNITUDE {
+                break;
+            }
+
+            let hit = query_pipeline.cast_shape(
+                &colliders,
+                &Isometry::translation(current.x, current.y),
+                &Vector::new(remaining.x, remaining.y),
+                &**shape,
+                1.,
+                CollisionLayer::Wall.only(),
+                Some(&filter),
+            );
+
+            let toi = match hit {
+                // Already overlapping something, e.g. right after arriving, so let it move out
+                Some((_, toi)) if toi.status != TOIStatus::Penetrating => toi,
+                _ => {
+                    current += remaining;
+                    break;
+                }
+            };
+
+            // Stop just short of the wall and keep the part of the movement that runs along it
+            let travelled = (toi.toi - CONTACT_SKIN / distance).max(0.);
+            current += remaining * travelled;
+
+            let normal = Vec2::new(toi.normal1.x, toi.normal1.y);
+            let left = remaining * (1. - travelled);
+            remaining = left - normal * left.dot(normal).min(0.);
+        }
+
+        let translation = physics_to_world(current).extend(position.translation.z);
+        position.step_to(translation);
+        body_position.next_position = Isometry::translation(current.x, current.y);
+    }
+}
+
+pub fn set_position_from_transform(
+    mut pos_query: Query<(&mut Position, &Transform), Added<Transform>>,
+) {
+    for (mut position, transform) in pos_query.iter_mut() {
+        position.teleport(transform.translation);
+    }
+}
+
+/// Places transforms between the last two steps, so movement looks smooth at any frame rate
+pub fn interpolate_transforms(
+    mut query: Query<(&Position, &mut Transform)>,
+    timesteps: Res<FixedTimesteps>,
+) {
+    let overstep = timesteps
+        .get(MOVEMENT_TIMESTEP_LABEL)
+        .map_or(1., |timestep| timestep.overstep_percentage() as f32);
+
+    for (position, mut transform) in query.iter_mut() {
+        // It has not been through a step yet, so its transform is still the one it was spawned with
+        if !position.placed {
+            continue;
+        }
+
+        // Only snapped to whole pixels on screen, the depth has to stay between the layers
+        let translation = position.previous.lerp(position.translation, overstep);
+        transform.translation = translation.xy().round().extend(position.translation.z);
+    }
+}
+
+fn apply_movements(mut mov_query: Query<(&mut Movements, &mut Velocity)>) {
+    for (mut movements, mut velocity) in mov_query.iter_mut() {
+        velocity.velocity = movements.step(MOVEMENT_TIMESTEP as f32);
+    }
+}
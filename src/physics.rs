@@ -1,15 +1,18 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, utils::HashSet};
 use bevy_ecs_tilemap::{Map, Tile};
 use bevy_rapier2d::{
     physics::{ColliderBundle, RapierConfiguration, RigidBodyBundle},
     prelude::{
-        ColliderPosition, ColliderShape, ColliderType, ContactEvent, IntersectionEvent,
-        RigidBodyType,
+        ColliderFlags, ColliderPosition, ColliderShape, ColliderType, ContactEvent,
+        InteractionGroups, IntersectionEvent, RigidBodyType,
     },
     render::ColliderDebugRender,
 };
 
-use crate::map::Wall;
+use crate::{
+    coords::{world_to_physics, MapGeometry},
+    map::{Destructible, Wall},
+};
 
 pub const PHYSICS_SCALE: f32 = 16.;
 pub struct PhysicsPlugin;
@@ -19,6 +22,7 @@ impl Plugin for PhysicsPlugin {
         app.add_startup_system(set_rapier_settings.system());
         app.add_system(display_events.system());
         app.add_system(setup_collisions.system());
+        app.add_system(setup_destructible_collisions.system());
     }
 }
 
@@ -28,6 +32,62 @@ fn set_rapier_settings(mut settings: ResMut<RapierConfiguration>) {
     settings.gravity = Vec2::ZERO.into();
 }
 
+/// What a collider is, it only interacts with colliders on the layers it is meant to.
+///
+/// E.g. the player's own bullets fly right through them and through each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CollisionLayer {
+    Player,
+    Enemy,
+    PlayerProjectile,
+    EnemyProjectile,
+    Wall,
+    Pickup,
+}
+
+impl CollisionLayer {
+    fn bit(self) -> u32 {
+        1 << self as u32
+    }
+
+    /// The layers colliders on this layer interact with, this has to go both ways
+    fn interacts_with(self) -> &'static [CollisionLayer] {
+        use CollisionLayer::*;
+
+        match self {
+            Player => &[Enemy, EnemyProjectile, Wall, Pickup],
+            Enemy => &[Player, Enemy, PlayerProjectile, Wall],
+            PlayerProjectile => &[Enemy, Wall],
+            EnemyProjectile => &[Player, Wall],
+            Wall => &[Player, Enemy, PlayerProjectile, EnemyProjectile],
+            Pickup => &[Player],
+        }
+    }
+
+    pub fn groups(self) -> InteractionGroups {
+        let filter = self
+            .interacts_with()
+            .iter()
+            .fold(0, |filter, layer| filter | layer.bit());
+
+        InteractionGroups::new(self.bit(), filter)
+    }
+
+    /// Groups for scene queries that only find colliders on this layer
+    pub fn only(self) -> InteractionGroups {
+        InteractionGroups::new(u32::MAX, self.bit())
+    }
+
+    /// Puts a collider on this layer, both for events and for resolving contacts
+    pub fn flags(self) -> ColliderFlags {
+        ColliderFlags {
+            collision_groups: self.groups(),
+            solver_groups: self.groups(),
+            ..Default::default()
+        }
+    }
+}
+
 /* A system that displays the events. */
 fn display_events(
     mut intersection_events: EventReader<IntersectionEvent>,
@@ -42,28 +102,239 @@ fn display_events(
     }
 }
 
+/// A static body covering a rectangle of wall tiles
+pub struct WallCollider;
+
+/// Covers the given tiles with rectangles, returned as their lower left tile and their size in tiles.
+///
+/// This is a greedy sweep, it does not find the smallest amount of rectangles but gets close for
+/// the mostly straight walls rooms consist of.
+pub fn merge_wall_tiles(tiles: &[UVec2]) -> Vec<(UVec2, UVec2)> {
+    let occupied: HashSet<(u32, u32)> = tiles.iter().map(|tile| (tile.x, tile.y)).collect();
+    let mut covered = HashSet::default();
+
+    let mut sorted: Vec<_> = occupied.iter().copied().collect();
+    sorted.sort_unstable_by_key(|&(x, y)| (y, x));
+
+    let is_free = |covered: &HashSet<(u32, u32)>, tile: (u32, u32)| {
+        occupied.contains(&tile) && !covered.contains(&tile)
+    };
+
+    let mut rectangles = vec![];
+    for (x, y) in sorted {
+        if covered.contains(&(x, y)) {
+            continue;
+        }
+
+        let mut width = 1;
+        while is_free(&covered, (x + width, y)) {
+            width += 1;
+        }
+
+        let mut height = 1;
+        while (0..width).all(|offset| is_free(&covered, (x + offset, y + height))) {
+            height += 1;
+        }
+
+        for offset_y in 0..height {
+            for offset_x in 0..width {
+                covered.insert((x + offset_x, y + offset_y));
+            }
+        }
+
+        rectangles.push((UVec2::new(x, y), UVec2::new(width, height)));
+    }
+
+    rectangles
+}
+
+/// Destructible tiles do not get a `Wall`, they keep a collider of their own so it can be removed
+/// once they get destroyed without rebuilding all the merged walls
 fn setup_collisions(
     mut commands: Commands,
-    tile_query: Query<(Entity, &UVec2), (Added<Tile>, With<Wall>)>,
-    map_query: Query<&Transform, With<Map>>,
+    new_walls: Query<(), (Added<Wall>, Without<Destructible>)>,
+    removed_walls: RemovedComponents<Wall>,
+    new_maps: Query<(), Added<Map>>,
+    wall_query: Query<&UVec2, (With<Wall>, Without<Destructible>)>,
+    wall_colliders: Query<Entity, With<WallCollider>>,
+    map_geometry: Option<Res<MapGeometry>>,
+) {
+    if new_walls.iter().next().is_none()
+        && removed_walls.iter().next().is_none()
+        && new_maps.iter().next().is_none()
+    {
+        return;
+    }
+
+    let map_geometry = if let Some(map_geometry) = map_geometry {
+        map_geometry
+    } else {
+        return;
+    };
+
+    for entity in wall_colliders.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    let walls: Vec<UVec2> = wall_query.iter().copied().collect();
+    let rectangles = merge_wall_tiles(&walls);
+
+    debug!(
+        "Merged {} wall tiles into {} colliders",
+        walls.len(),
+        rectangles.len()
+    );
+
+    for (start, size) in rectangles {
+        let (center, half_extents) = map_geometry.tile_rect_to_world(start, size);
+        let half_extents = world_to_physics(half_extents);
+
+        commands
+            .spawn()
+            .insert(WallCollider)
+            .insert_bundle(ColliderBundle {
+                shape: ColliderShape::cuboid(half_extents.x, half_extents.y),
+                collider_type: ColliderType::Solid,
+                position: ColliderPosition(world_to_physics(center).extend(0.0).into()),
+                flags: CollisionLayer::Wall.flags(),
+                ..Default::default()
+            })
+            .insert_bundle(RigidBodyBundle {
+                body_type: RigidBodyType::Static,
+                ..Default::default()
+            })
+            .insert(ColliderDebugRender::with_id(2));
+    }
+}
+
+fn setup_destructible_collisions(
+    mut commands: Commands,
+    // Crates placed in the editor become destructible after their tile has been built
+    tile_query: Query<
+        (Entity, &UVec2),
+        (
+            With<Tile>,
+            With<Destructible>,
+            Or<(Added<Tile>, Added<Destructible>)>,
+        ),
+    >,
+    map_geometry: Option<Res<MapGeometry>>,
 ) {
-    let map_position = map_query.single().unwrap();
+    let map_geometry = if let Some(map_geometry) = map_geometry {
+        map_geometry
+    } else {
+        return;
+    };
+
     for (entity, position) in tile_query.iter() {
+        let (center, half_extents) = map_geometry.tile_rect_to_world(*position, UVec2::new(1, 1));
+        let half_extents = world_to_physics(half_extents);
+
         commands
             .entity(entity)
-            .insert(ColliderBundle {
-                shape: ColliderShape::cuboid(1., 1.),
+            .insert_bundle(ColliderBundle {
+                shape: ColliderShape::cuboid(half_extents.x, half_extents.y),
                 collider_type: ColliderType::Solid,
-                position: ColliderPosition(
-                    ((position.as_f32().extend(0.0) + map_position.translation) / PHYSICS_SCALE)
-                        .into(),
-                ),
+                position: ColliderPosition(world_to_physics(center).extend(0.0).into()),
+                flags: CollisionLayer::Wall.flags(),
                 ..Default::default()
             })
-            .insert(RigidBodyBundle {
+            .insert_bundle(RigidBodyBundle {
                 body_type: RigidBodyType::Static,
                 ..Default::default()
             })
-            .insert(ColliderDebugRender::with_id(2));
+            .insert(ColliderDebugRender::with_id(3));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::utils::HashMap;
+
+    use super::*;
+
+    /// How often every tile is covered by the rectangles
+    fn coverage(rectangles: &[(UVec2, UVec2)]) -> HashMap<(u32, u32), u32> {
+        let mut coverage = HashMap::default();
+
+        for (start, size) in rectangles {
+            for y in start.y..start.y + size.y {
+                for x in start.x..start.x + size.x {
+                    *coverage.entry((x, y)).or_insert(0) += 1;
+                }
+            }
+        }
+
+        coverage
+    }
+
+    fn assert_covers_exactly(tiles: &[UVec2]) {
+        let coverage = coverage(&merge_wall_tiles(tiles));
+        let expected: HashSet<(u32, u32)> = tiles.iter().map(|tile| (tile.x, tile.y)).collect();
+
+        for tile in expected.iter() {
+            assert_eq!(coverage.get(tile), Some(&1), "tile {:?}", tile);
+        }
+        for tile in coverage.keys() {
+            assert!(expected.contains(tile), "tile {:?} is not a wall", tile);
+        }
+    }
+
+    fn bordered_room(width: u32, height: u32) -> Vec<UVec2> {
+        let mut tiles = vec![];
+        for y in 0..height {
+            for x in 0..width {
+                if x == 0 || y == 0 || x == width - 1 || y == height - 1 {
+                    tiles.push(UVec2::new(x, y));
+                }
+            }
+        }
+        tiles
+    }
+
+    #[test]
+    fn no_tiles_give_no_rectangles() {
+        assert!(merge_wall_tiles(&[]).is_empty());
+    }
+
+    #[test]
+    fn bordered_room_merges_into_four_rectangles() {
+        let tiles = bordered_room(10, 8);
+
+        assert_eq!(merge_wall_tiles(&tiles).len(), 4);
+        assert_covers_exactly(&tiles);
+    }
+
+    #[test]
+    fn blocks_merge_into_one_rectangle() {
+        let tiles: Vec<_> = (2..5)
+            .flat_map(|y| (3..7).map(move |x| UVec2::new(x, y)))
+            .collect();
+
+        assert_eq!(
+            merge_wall_tiles(&tiles),
+            vec![(UVec2::new(3, 2), UVec2::new(4, 3))]
+        );
+    }
+
+    #[test]
+    fn every_tile_is_covered_exactly_once() {
+        let mut shelves = bordered_room(12, 12);
+        shelves.extend((2..6).map(|x| UVec2::new(x, 4)));
+        shelves.extend((7..10).map(|x| UVec2::new(x, 4)));
+        shelves.extend((3..9).map(|y| UVec2::new(6, y)));
+        // Duplicates only get covered once as well
+        shelves.push(UVec2::new(6, 4));
+        assert_covers_exactly(&shelves);
+
+        // Scattered tiles from a fixed sequence of pseudo random numbers
+        let mut state = 7u32;
+        let scattered: Vec<_> = (0..200)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                UVec2::new((state >> 8) % 16, (state >> 20) % 16)
+            })
+            .collect();
+        assert_covers_exactly(&scattered);
     }
 }
@@ -1,31 +1,83 @@
-use std::f32::consts::PI;
+use std::{f32::consts::PI, time::Duration};
 
 use bevy::{math::Vec3Swizzles, prelude::*, render::camera::Camera};
 use bevy_rapier2d::{physics::{ColliderBundle, RigidBodyBundle}, prelude::{ColliderMassProps, ColliderShape, ColliderType, RigidBodyActivation, RigidBodyType}};
 
 use crate::{
     assets::GameAssets,
-    movement::{Movement, MovementBundle, MovementModifier, Movements},
+    combat::Health,
+    coords::{pixels_to_physics, MapGeometry},
+    input::{Action, ActionState},
+    map::{PlayerArrival, SpawnPoint},
+    movement::{
+        Blend, Easing, Movement, MovementBundle, MovementCalculation, MovementChannel,
+        MovementModifier, MovementStage, Movements, Position, Velocity, MOVEMENT_TIMESTEP,
+    },
+    physics::CollisionLayer,
+    weapons::Loadout,
     MainCamera, WINDOW_SCALE_FACTOR,
 };
 
 #[derive(Debug, Eq, PartialEq, PartialOrd, Clone, Copy, Hash, SystemLabel)]
 struct MouseMovementUpdate;
 
+#[derive(Debug, Eq, PartialEq, PartialOrd, Clone, Copy, Hash, SystemLabel)]
+struct AimUpdate;
+
+/// Places the player at their arrival point once a room has been built
+#[derive(Debug, Eq, PartialEq, PartialOrd, Clone, Copy, Hash, SystemLabel)]
+pub struct PlayerPlacement;
+
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.add_startup_system(spawn_player.system());
-        app.add_system(handle_movement.system());
+        app.add_system_to_stage(
+            MovementStage,
+            handle_movement
+                .system()
+                .label(MovementCalculation::Input)
+                .before(MovementCalculation::Movements),
+        );
+        app.add_system(request_dash.system());
+        app.add_system(switch_weapon.system());
+        app.add_system_to_stage(
+            MovementStage,
+            dash.system()
+                .label(MovementCalculation::Input)
+                .before(MovementCalculation::Movements),
+        );
+        app.add_system_to_stage(MovementStage, wear_off_invulnerability.system());
+        app.add_system(move_to_spawn_point.system().label(PlayerPlacement));
+        app.add_system(move_to_actor_depth.system());
         app.add_system(update_mouse_position.system().label(MouseMovementUpdate));
-        app.add_system(look_at_player.system().after(MouseMovementUpdate));
+        app.add_system(
+            aim_with_gamepad
+                .system()
+                .label(AimUpdate)
+                .after(MouseMovementUpdate),
+        );
+        app.add_system(look_at_player.system().after(AimUpdate));
     }
 }
 
 pub struct Player;
 
-fn spawn_player(mut commands: Commands, game_assets: Res<GameAssets>) {
+/// The radius of the player's collider in pixels, it has to stay below half a tile so the player
+/// fits through doors and the gaps between shelves, which are only a single tile wide
+const PLAYER_RADIUS: f32 = 6.;
+
+const PLAYER_HEALTH: u32 = 5;
+
+/// The weapons the player starts out with
+const PLAYER_ARSENAL: &str = "player.arsenal";
+
+fn spawn_player(
+    mut commands: Commands,
+    game_assets: Res<GameAssets>,
+    asset_server: Res<AssetServer>,
+) {
     commands
         .spawn()
         .insert(Player)
@@ -33,6 +85,9 @@ fn spawn_player(mut commands: Commands, game_assets: Res<GameAssets>) {
         .insert(PlayerMouse {
             position: Vec2::ZERO,
         })
+        .insert(Dash::default())
+        .insert(Health::new(PLAYER_HEALTH))
+        .insert(Loadout::new(asset_server.load(PLAYER_ARSENAL)))
         .insert_bundle(SpriteSheetBundle {
             texture_atlas: game_assets.texture_atlas_handle.clone(),
             transform: Transform::from_xyz(0., 0., 1.),
@@ -45,40 +100,71 @@ fn spawn_player(mut commands: Commands, game_assets: Res<GameAssets>) {
             ..Default::default()
         })
         .insert_bundle(ColliderBundle {
-            shape: ColliderShape::ball(0.5),
+            shape: ColliderShape::ball(pixels_to_physics(PLAYER_RADIUS)),
             collider_type: ColliderType::Sensor,
+            flags: CollisionLayer::Player.flags(),
             ..Default::default()
         });
 }
 
+fn move_to_spawn_point(
+    spawn_query: Query<(&UVec2, &SpawnPoint), Added<SpawnPoint>>,
+    map_geometry: Option<Res<MapGeometry>>,
+    arrival: Res<PlayerArrival>,
+    mut player_query: Query<(&mut Transform, &mut Position), With<Player>>,
+) {
+    let map_geometry = if let Some(map_geometry) = map_geometry {
+        map_geometry
+    } else {
+        return;
+    };
+
+    for (tile_position, spawn) in spawn_query.iter() {
+        if *spawn != arrival.0 {
+            continue;
+        }
+
+        let world_position = map_geometry.tile_to_world(*tile_position);
+
+        for (mut transform, mut position) in player_query.iter_mut() {
+            transform.translation = world_position.extend(transform.translation.z);
+            position.teleport(transform.translation);
+        }
+    }
+}
+
+fn move_to_actor_depth(
+    map_geometry: Option<Res<MapGeometry>>,
+    mut player_query: Query<(&mut Transform, &mut Position), With<Player>>,
+) {
+    let map_geometry = match map_geometry {
+        Some(map_geometry) if map_geometry.is_changed() => map_geometry,
+        _ => return,
+    };
+
+    for (mut transform, mut position) in player_query.iter_mut() {
+        transform.translation.z = map_geometry.actor_depth;
+        position.teleport(transform.translation);
+    }
+}
+
 const MAX_SPEED: f32 = 256.;
+const PLAYER_INPUT: MovementChannel = MovementChannel("player_input");
 
 fn handle_movement(
-    player_input: Res<Input<KeyCode>>,
+    action_state: Res<ActionState>,
     mut player_query: Query<(&mut Movements,), With<Player>>,
 ) {
     for (mut movements,) in player_query.iter_mut() {
-        let forward = Vec3::Y;
-        let right = Vec3::X;
-        let mut acceleration = Vec3::ZERO;
-        for key in player_input.get_pressed() {
-            match key {
-                KeyCode::W => acceleration += forward,
-                KeyCode::S => acceleration -= forward,
-                KeyCode::A => acceleration -= right,
-                KeyCode::D => acceleration += right,
-                _ => (),
-            }
-        }
+        let movement = action_state.movement().extend(0.);
 
-        acceleration *= MAX_SPEED * 2.;
-
-        if !acceleration.is_nan() && acceleration != Vec3::ZERO {
+        if movement != Vec3::ZERO {
             movements.add(Movement::new(
-                "player_input",
+                PLAYER_INPUT,
                 MovementModifier::Momentum {
-                    acceleration,
-                    maximal_velocity: Some(MAX_SPEED),
+                    acceleration: movement * MAX_SPEED * 2.,
+                    // A stick tilted part of the way walks slower
+                    maximal_velocity: Some(MAX_SPEED * movement.length()),
                     dampening: 0.000000001,
                 },
             ));
@@ -86,6 +172,139 @@ fn handle_movement(
     }
 }
 
+const DASH: MovementChannel = MovementChannel("dash");
+/// How fast the player dashes in pixels per second at the start of a dash
+const DASH_SPEED: f32 = 768.;
+const DASH_DURATION: f32 = 0.15;
+const DASH_COOLDOWN: f32 = 0.6;
+/// How long the player cannot be hurt after starting a dash, this outlasts the dash a little
+const DASH_INVULNERABILITY: f32 = 0.25;
+
+/// A short burst of speed the player can use to get out of the way
+pub struct Dash {
+    cooldown: Timer,
+    /// The key can be pressed on a frame without a movement step, so the dash waits for the next one
+    requested: bool,
+}
+
+impl Default for Dash {
+    fn default() -> Self {
+        let mut cooldown = Timer::from_seconds(DASH_COOLDOWN, false);
+        cooldown.tick(Duration::from_secs_f32(DASH_COOLDOWN));
+
+        Dash {
+            cooldown,
+            requested: false,
+        }
+    }
+}
+
+impl Dash {
+    pub fn is_ready(&self) -> bool {
+        self.cooldown.finished()
+    }
+
+    /// How far the cooldown has come along, from 0 right after dashing to 1 once it can be used
+    /// again
+    pub fn readiness(&self) -> f32 {
+        self.cooldown.percent()
+    }
+}
+
+/// Nothing can hurt an entity while it has this, it removes itself once the time is up
+pub struct Invulnerable {
+    timer: Timer,
+}
+
+impl Invulnerable {
+    pub fn from_seconds(seconds: f32) -> Self {
+        Invulnerable {
+            timer: Timer::from_seconds(seconds, false),
+        }
+    }
+}
+
+fn request_dash(action_state: Res<ActionState>, mut dash_query: Query<&mut Dash, With<Player>>) {
+    if !action_state.just_pressed(Action::Dash) {
+        return;
+    }
+
+    for mut dash in dash_query.iter_mut() {
+        dash.requested = true;
+    }
+}
+
+fn dash(
+    mut commands: Commands,
+    mut player_query: Query<
+        (Entity, &mut Dash, &mut Movements, &Velocity, &Transform),
+        With<Player>,
+    >,
+) {
+    for (entity, mut dash, mut movements, velocity, transform) in player_query.iter_mut() {
+        dash.cooldown
+            .tick(Duration::from_secs_f64(MOVEMENT_TIMESTEP));
+
+        if !std::mem::take(&mut dash.requested) || !dash.is_ready() {
+            continue;
+        }
+
+        // Dash where the player is going, standing still they dash where they are looking
+        let direction = if velocity.velocity().length() > 0. {
+            velocity.velocity().normalize()
+        } else {
+            transform.rotation * Vec3::Y
+        };
+
+        movements.add(
+            Movement::new(
+                DASH,
+                MovementModifier::Timed {
+                    velocity: direction * DASH_SPEED,
+                    duration: DASH_DURATION,
+                    easing: Easing::EaseOut,
+                },
+            )
+            // Walking does not steer a dash
+            .with_blend(Blend::Override)
+            .with_priority(10),
+        );
+
+        dash.cooldown.reset();
+        commands
+            .entity(entity)
+            .insert(Invulnerable::from_seconds(DASH_INVULNERABILITY));
+    }
+}
+
+fn wear_off_invulnerability(
+    mut commands: Commands,
+    mut invulnerable_query: Query<(Entity, &mut Invulnerable)>,
+) {
+    for (entity, mut invulnerable) in invulnerable_query.iter_mut() {
+        invulnerable
+            .timer
+            .tick(Duration::from_secs_f64(MOVEMENT_TIMESTEP));
+
+        if invulnerable.timer.finished() {
+            commands.entity(entity).remove::<Invulnerable>();
+        }
+    }
+}
+
+fn switch_weapon(
+    action_state: Res<ActionState>,
+    mut loadout_query: Query<&mut Loadout, With<Player>>,
+) {
+    if !action_state.just_pressed(Action::NextWeapon) {
+        return;
+    }
+
+    for mut loadout in loadout_query.iter_mut() {
+        loadout.selected = loadout.selected.wrapping_add(1);
+    }
+}
+
 fn look_at_player(mut transform_queries: Query<(&mut Transform, &PlayerMouse), With<Player>>) {
     for (mut trans, player_mouse) in transform_queries.iter_mut() {
         let dir = player_mouse.position - trans.translation.xy();
@@ -94,10 +313,18 @@ fn look_at_player(mut transform_queries: Query<(&mut Transform, &PlayerMouse), W
     }
 }
 
+/// Where the player is aiming, this follows the cursor or the right stick of a gamepad
 pub struct PlayerMouse {
     position: Vec2,
 }
 
+impl PlayerMouse {
+    /// The point in the world being aimed at
+    pub fn position(&self) -> Vec2 {
+        self.position
+    }
+}
+
 fn update_mouse_position(
     mut mouse_input: EventReader<CursorMoved>,
     mut mouse_query: Query<(&mut PlayerMouse,), With<Player>>,
@@ -124,3 +351,32 @@ fn update_mouse_position(
         info!("Position: {:?}", player_mouse.position);
     }
 }
+
+/// How far in front of the player the aim is put when aiming with a stick
+const STICK_AIM_DISTANCE: f32 = 48.;
+
+fn aim_with_gamepad(
+    action_state: Res<ActionState>,
+    mut last_aim: Local<Option<Vec2>>,
+    mut player_query: Query<(&mut PlayerMouse, &Transform), With<Player>>,
+) {
+    if !action_state.using_gamepad() {
+        *last_aim = None;
+        return;
+    }
+
+    // Letting go of the stick keeps aiming in the same direction
+    if let Some(aim) = action_state.aim() {
+        *last_aim = Some(aim);
+    }
+
+    let aim = if let Some(aim) = *last_aim {
+        aim
+    } else {
+        return;
+    };
+
+    for (mut player_mouse, transform) in player_query.iter_mut() {
+        player_mouse.position = transform.translation.xy() + aim * STICK_AIM_DISTANCE;
+    }
+}
@@ -37,7 +37,8 @@ void main() {
 
     float frames = float(Vertex_Texture.w - Vertex_Texture.z);
 
-    float current_animation_frame = fract(time * Vertex_Position.z) * frames;
+    // Animations loop over [start, end), static tiles have start == end
+    float current_animation_frame = float(Vertex_Texture.z) + fract(time * Vertex_Position.z) * frames;
 
     current_animation_frame = clamp(current_animation_frame, float(Vertex_Texture.z), float(Vertex_Texture.w));
 
@@ -0,0 +1,356 @@
+use std::path::PathBuf;
+
+use anyhow::bail;
+use bevy::{
+    asset::{AssetLoader, AssetPath, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use serde::Deserialize;
+
+pub struct WeaponsPlugin;
+
+impl Plugin for WeaponsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_asset::<WeaponDefinition>();
+        app.init_asset_loader::<WeaponDefinitionLoader>();
+        app.add_asset::<Arsenal>();
+        app.init_asset_loader::<ArsenalLoader>();
+        app.add_system(equip_weapons.system());
+        app.add_system(reload_weapons.system());
+    }
+}
+
+/// How a weapon fires while the trigger is held
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+pub enum FireMode {
+    /// One shot per pull of the trigger
+    #[default]
+    SemiAuto,
+    /// The given amount of shots per pull of the trigger, they are fired even if it gets released
+    Burst(u32),
+    /// Fires for as long as the trigger is held
+    FullAuto,
+}
+
+/// Everything that makes up a weapon, loaded from `.weapon` files
+#[derive(Debug, Clone, Deserialize, TypeUuid)]
+#[uuid = "2b8e6f14-a0c3-4d97-b5e1-7c9f03d2e846"]
+pub struct WeaponDefinition {
+    pub name: String,
+    /// Shots per second, also between the shots of a burst
+    pub fire_rate: f32,
+    #[serde(default)]
+    pub fire_mode: FireMode,
+    /// The angle in degrees the projectiles of a shot are spread over
+    #[serde(default)]
+    pub spread: f32,
+    /// How many projectiles a single shot fires
+    #[serde(default = "WeaponDefinition::single_projectile")]
+    pub projectile_count: u32,
+    /// How much health a projectile takes away
+    #[serde(default = "WeaponDefinition::default_damage")]
+    pub damage: u32,
+    /// In pixels per second
+    pub projectile_speed: f32,
+    /// The radius of a projectile's collider in pixels
+    pub projectile_radius: f32,
+    /// In seconds, projectiles that have not hit anything by then disappear
+    #[serde(default = "WeaponDefinition::default_lifetime")]
+    pub projectile_lifetime: f32,
+    /// How often a projectile bounces off before it disappears on impact
+    #[serde(default)]
+    pub projectile_bounces: u32,
+    /// Where the projectile is in the entity texture atlas
+    pub sprite_index: u32,
+    pub magazine_size: u32,
+    /// In seconds, reloading starts once the magazine is empty
+    pub reload_time: f32,
+}
+
+impl WeaponDefinition {
+    fn single_projectile() -> u32 {
+        1
+    }
+
+    fn default_damage() -> u32 {
+        1
+    }
+
+    fn default_lifetime() -> f32 {
+        2.
+    }
+}
+
+#[derive(Default)]
+pub struct WeaponDefinitionLoader;
+
+impl AssetLoader for WeaponDefinitionLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let definition: WeaponDefinition = ron::de::from_bytes(bytes)?;
+
+            if definition.fire_rate <= 0. {
+                bail!("Weapon {:?} needs a fire rate above 0", definition.name);
+            }
+            if definition.magazine_size == 0 {
+                bail!(
+                    "Weapon {:?} needs room for at least one round",
+                    definition.name
+                );
+            }
+
+            load_context.set_default_asset(LoadedAsset::new(definition));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["weapon"]
+    }
+}
+
+/// The weapons someone can pick from, loaded from `.arsenal` files listing weapon files
+#[derive(Debug, TypeUuid)]
+#[uuid = "e6a0d3c8-19f4-4b2a-a7d5-5f83c1b9e072"]
+pub struct Arsenal {
+    pub weapons: Vec<Handle<WeaponDefinition>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ArsenalDescription {
+    weapons: Vec<String>,
+}
+
+#[derive(Default)]
+pub struct ArsenalLoader;
+
+impl AssetLoader for ArsenalLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let description: ArsenalDescription = ron::de::from_bytes(bytes)?;
+
+            let weapons = description
+                .weapons
+                .iter()
+                .map(|path| load_context.get_handle(path.as_str()))
+                .collect();
+
+            let dependencies = description
+                .weapons
+                .iter()
+                .map(|path| AssetPath::new(PathBuf::from(path), None))
+                .collect();
+
+            load_context.set_default_asset(
+                LoadedAsset::new(Arsenal { weapons }).with_dependencies(dependencies),
+            );
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["arsenal"]
+    }
+}
+
+/// The arsenal of an entity and which of its weapons is in hand
+pub struct Loadout {
+    pub arsenal: Handle<Arsenal>,
+    /// Which weapon of the arsenal is in hand, this wraps around
+    pub selected: usize,
+    /// The arsenal slot of the `Weapon` in hand
+    equipped: Option<usize>,
+    /// The weapons that have been put away by arsenal slot, they keep their ammo and pick up
+    /// reloading where they left off once they are taken out again
+    holstered: Vec<Option<Weapon>>,
+}
+
+impl Loadout {
+    pub fn new(arsenal: Handle<Arsenal>) -> Self {
+        Loadout {
+            arsenal,
+            selected: 0,
+            equipped: None,
+            holstered: vec![],
+        }
+    }
+}
+
+/// The weapon in hand, its ammunition can be shown in the UI
+pub struct Weapon {
+    pub definition: Handle<WeaponDefinition>,
+    pub ammo: u32,
+    /// Runs between two shots
+    cooldown: Timer,
+    reload: Option<Timer>,
+    shots_fired: u32,
+    /// Shots of the current burst that still have to be fired
+    burst_left: u32,
+}
+
+impl Weapon {
+    /// A weapon with a full magazine that can be fired right away
+    pub fn new(handle: Handle<WeaponDefinition>, definition: &WeaponDefinition) -> Self {
+        let mut cooldown = Timer::from_seconds(1. / definition.fire_rate, false);
+        cooldown.tick(cooldown.duration());
+
+        Weapon {
+            definition: handle,
+            ammo: definition.magazine_size,
+            cooldown,
+            reload: None,
+            shots_fired: 0,
+            burst_left: 0,
+        }
+    }
+
+    pub fn is_reloading(&self) -> bool {
+        self.reload.is_some()
+    }
+
+    pub fn can_fire(&self) -> bool {
+        !self.is_reloading() && self.ammo > 0 && self.cooldown.finished()
+    }
+
+    /// Whether the weapon fires this frame, given whether the trigger is held and whether it has
+    /// just been pulled
+    pub fn pull_trigger(
+        &mut self,
+        definition: &WeaponDefinition,
+        held: bool,
+        pulled: bool,
+    ) -> bool {
+        let wants_to_fire = match definition.fire_mode {
+            FireMode::SemiAuto => pulled,
+            FireMode::Burst(shots) => {
+                if pulled && self.burst_left == 0 && self.can_fire() {
+                    self.burst_left = shots;
+                }
+                self.burst_left > 0
+            }
+            FireMode::FullAuto => held,
+        };
+
+        wants_to_fire && self.can_fire()
+    }
+
+    /// Uses up a round and returns the angle offsets in degrees of the projectiles to spawn.
+    ///
+    /// Several projectiles get spread out evenly, a single one lands somewhere within the spread.
+    /// That spot is picked from a low discrepancy sequence, so consecutive shots cover the whole
+    /// spread without clumping.
+    pub fn fire(&mut self, definition: &WeaponDefinition) -> Vec<f32> {
+        self.ammo = self.ammo.saturating_sub(1);
+        self.shots_fired = self.shots_fired.wrapping_add(1);
+        self.cooldown = Timer::from_seconds(1. / definition.fire_rate, false);
+
+        self.burst_left = self.burst_left.saturating_sub(1);
+
+        if self.ammo == 0 {
+            self.reload = Some(Timer::from_seconds(definition.reload_time, false));
+            self.burst_left = 0;
+        }
+
+        let count = definition.projectile_count.max(1);
+        if count == 1 {
+            const GOLDEN_RATIO_CONJUGATE: f32 = 0.618_034;
+            let offset = (self.shots_fired as f32 * GOLDEN_RATIO_CONJUGATE).fract() - 0.5;
+            return vec![offset * definition.spread];
+        }
+
+        (0..count)
+            .map(|index| (index as f32 / (count - 1) as f32 - 0.5) * definition.spread)
+            .collect()
+    }
+}
+
+fn equip_weapons(
+    mut commands: Commands,
+    arsenals: Res<Assets<Arsenal>>,
+    definitions: Res<Assets<WeaponDefinition>>,
+    mut loadout_query: Query<(Entity, &mut Loadout, Option<&mut Weapon>)>,
+) {
+    for (entity, mut loadout, weapon) in loadout_query.iter_mut() {
+        let arsenal = match arsenals.get(&loadout.arsenal) {
+            Some(arsenal) if !arsenal.weapons.is_empty() => arsenal,
+            _ => continue,
+        };
+
+        let slot = loadout.selected % arsenal.weapons.len();
+        if loadout.equipped == Some(slot) && weapon.is_some() {
+            continue;
+        }
+
+        let selected = &arsenal.weapons[slot];
+        let definition = if let Some(definition) = definitions.get(selected) {
+            definition
+        } else {
+            continue;
+        };
+
+        let loadout = &mut *loadout;
+        if loadout.holstered.len() < arsenal.weapons.len() {
+            loadout
+                .holstered
+                .resize_with(arsenal.weapons.len(), || None);
+        }
+
+        let drawn = loadout.holstered[slot]
+            .take()
+            .unwrap_or_else(|| Weapon::new(selected.clone(), definition));
+        info!("Equipped {}", definition.name);
+
+        match weapon {
+            Some(mut weapon) => {
+                let put_away = std::mem::replace(&mut *weapon, drawn);
+                if let Some(holster) = loadout
+                    .equipped
+                    .and_then(|equipped| loadout.holstered.get_mut(equipped))
+                {
+                    *holster = Some(put_away);
+                }
+            }
+            None => {
+                commands.entity(entity).insert(drawn);
+            }
+        }
+
+        loadout.equipped = Some(slot);
+    }
+}
+
+fn reload_weapons(
+    time: Res<Time>,
+    mut weapon_query: Query<&mut Weapon>,
+    definitions: Res<Assets<WeaponDefinition>>,
+) {
+    for mut weapon in weapon_query.iter_mut() {
+        let weapon = &mut *weapon;
+        weapon.cooldown.tick(time.delta());
+
+        let reload = if let Some(reload) = &mut weapon.reload {
+            reload
+        } else {
+            continue;
+        };
+
+        if !reload.tick(time.delta()).finished() {
+            continue;
+        }
+
+        if let Some(definition) = definitions.get(&weapon.definition) {
+            weapon.ammo = definition.magazine_size;
+        }
+        weapon.reload = None;
+    }
+}